@@ -83,7 +83,9 @@ export const useChannelStore = create<ChannelState>((set, get) => {
         channel_id: string;
         data: string;
       };
-      useMlsStore.getState().processIncomingMlsMessage(channel_id, "proposal", mlsData);
+      const mls = useMlsStore.getState();
+      mls.processIncomingMlsMessage(channel_id, "proposal", mlsData);
+      mls.commitPendingProposals(channel_id).catch(console.error);
     },
     onReactionAdded: (data) => {
       const reactionData = data as { message_id: string; emoji: string; user_id: string };
@@ -0,0 +1,81 @@
+import { describe, it, expect, vi, beforeEach } from "vitest";
+
+const client = vi.hoisted(() => ({
+  initAsync: vi.fn(async () => {}),
+  importSigningKey: vi.fn(() => ({})),
+  createSession: vi.fn(),
+  processMessage: vi.fn(),
+  getPendingProposals: vi.fn(),
+  commitPendingProposals: vi.fn(() => ({
+    commit: new Uint8Array([1]),
+    groupInfo: new Uint8Array([2]),
+  })),
+  getEpoch: vi.fn(() => 3),
+  confirmPendingCommit: vi.fn(),
+  discardPendingCommit: vi.fn(),
+  destroySession: vi.fn(),
+}));
+
+vi.mock("@cairn/proto", () => ({
+  MlsClient: vi.fn(function () {
+    return client;
+  }),
+}));
+
+vi.mock("../api/mls.ts", () => ({
+  keyPackageCount: vi.fn(async () => ({ count: 100 })),
+  storeCommit: vi.fn(async () => ({ id: "commit" })),
+  storeGroupInfo: vi.fn(async () => ({})),
+  getPendingMessages: vi.fn(),
+  ackMessages: vi.fn(async () => {}),
+}));
+
+import * as mlsApi from "../api/mls.ts";
+import { useMlsStore } from "./mlsStore.ts";
+
+const keys = { publicKey: new Uint8Array(32), privateKey: new Uint8Array(64) };
+
+describe("mlsStore proposals", () => {
+  beforeEach(async () => {
+    vi.clearAllMocks();
+    useMlsStore.getState().destroy();
+    await useMlsStore.getState().initMls(keys);
+  });
+
+  it("commits received proposals once the server accepts the commit", async () => {
+    client.getPendingProposals.mockReturnValue([{ proposalType: "Remove" }]);
+    vi.mocked(mlsApi.getPendingMessages).mockResolvedValue({
+      messages: [{ id: "m1", message_type: "proposal", data: btoa("p") }],
+    } as never);
+
+    await useMlsStore.getState().processPendingMessages("chan");
+
+    expect(client.processMessage).toHaveBeenCalledTimes(1);
+    expect(mlsApi.storeCommit).toHaveBeenCalledWith("chan", btoa("\x01"), 4);
+    expect(client.confirmPendingCommit).toHaveBeenCalledTimes(1);
+    expect(client.discardPendingCommit).not.toHaveBeenCalled();
+    expect(mlsApi.storeGroupInfo).toHaveBeenCalledWith("chan", btoa("\x02"), 4);
+  });
+
+  it("discards the commit when another member's won", async () => {
+    client.getPendingProposals.mockReturnValue([{ proposalType: "Update" }]);
+    vi.mocked(mlsApi.storeCommit).mockRejectedValueOnce(new Error("epoch taken"));
+
+    await expect(
+      useMlsStore.getState().commitPendingProposals("chan"),
+    ).rejects.toThrow("epoch taken");
+    expect(client.discardPendingCommit).toHaveBeenCalledTimes(1);
+    expect(client.confirmPendingCommit).not.toHaveBeenCalled();
+  });
+
+  it("leaves Add proposals to their proposer", async () => {
+    client.getPendingProposals.mockReturnValue([
+      { proposalType: "Remove" },
+      { proposalType: "Add" },
+    ]);
+
+    await useMlsStore.getState().commitPendingProposals("chan");
+    expect(client.commitPendingProposals).not.toHaveBeenCalled();
+    expect(mlsApi.storeCommit).not.toHaveBeenCalled();
+  });
+});
@@ -80,6 +80,14 @@ interface MlsState {
     data: string,
   ) => void;
 
+  /**
+   * Commit the proposals queued in a channel's group. The group can't send
+   * while any are queued, so every member tries; the server takes the first
+   * commit for the epoch and the others are discarded. Add proposals are left
+   * to the member who proposed them, who knows whom to send the Welcome to.
+   */
+  commitPendingProposals: (channelId: string) => Promise<void>;
+
   /** Fetch and process any pending MLS messages for a channel. */
   processPendingMessages: (channelId: string) => Promise<void>;
 
@@ -132,7 +140,7 @@ export const useMlsStore = create<MlsState>((set, get) => ({
       const { count } = await mlsApi.keyPackageCount();
       if (count >= MIN_KEY_PACKAGES) return;
 
-      const packages = mlsClient.generateSessionKeyPackages(KEY_PACKAGE_BATCH);
+      const packages = mlsClient.generateKeyPackagePool(KEY_PACKAGE_BATCH);
       const encoded = packages.map((pkg) => base64Encode(pkg.keyPackageData));
       await mlsApi.uploadKeyPackages(encoded);
     } catch (err) {
@@ -171,13 +179,20 @@ export const useMlsStore = create<MlsState>((set, get) => ({
         const keyPackageBytes = base64Decode(key_package);
         const result = mlsClient.addMember(groupId, keyPackageBytes);
 
-        // Store commit for existing members
-        const epoch = mlsClient.getEpoch(groupId);
-        await mlsApi.storeCommit(
-          channel.id,
-          base64Encode(result.commit),
-          epoch,
-        );
+        // Store commit for existing members; apply it locally only once
+        // the server has accepted it
+        const epoch = mlsClient.getEpoch(groupId) + 1;
+        try {
+          await mlsApi.storeCommit(
+            channel.id,
+            base64Encode(result.commit),
+            epoch,
+          );
+        } catch (err) {
+          mlsClient.discardPendingCommit(groupId);
+          throw err;
+        }
+        mlsClient.confirmPendingCommit(groupId);
 
         // Send welcome to new member
         await mlsApi.storeWelcome(
@@ -192,7 +207,11 @@ export const useMlsStore = create<MlsState>((set, get) => ({
 
     // Store group info for late joiners
     const epoch = mlsClient.getEpoch(groupId);
-    await mlsApi.storeGroupInfo(channel.id, base64Encode(groupId), epoch);
+    await mlsApi.storeGroupInfo(
+      channel.id,
+      base64Encode(mlsClient.exportGroupInfo(groupId)),
+      epoch,
+    );
 
     return channel;
   },
@@ -213,12 +232,21 @@ export const useMlsStore = create<MlsState>((set, get) => ({
     const keyPackageBytes = base64Decode(key_package);
     const result = mlsClient.addMember(groupId, keyPackageBytes);
 
-    const epoch = mlsClient.getEpoch(groupId);
+    const epoch = mlsClient.getEpoch(groupId) + 1;
+
+    // The commit only takes effect once the server accepts it; the Welcome
+    // is useless to the new member before that
+    try {
+      await mlsApi.storeCommit(channelId, base64Encode(result.commit), epoch);
+    } catch (err) {
+      mlsClient.discardPendingCommit(groupId);
+      throw err;
+    }
+    mlsClient.confirmPendingCommit(groupId);
 
-    // Store commit + welcome on server
     await Promise.all([
-      mlsApi.storeCommit(channelId, base64Encode(result.commit), epoch),
       mlsApi.storeWelcome(channelId, base64Encode(result.welcome), userId),
+      mlsApi.storeGroupInfo(channelId, base64Encode(result.groupInfo), epoch),
     ]);
   },
 
@@ -284,6 +312,37 @@ export const useMlsStore = create<MlsState>((set, get) => ({
     }
   },
 
+  commitPendingProposals: async (channelId) => {
+    if (!mlsClient || !get().initialized) return;
+
+    const groupId = channelIdToGroupId(channelId);
+    const proposals = mlsClient.getPendingProposals(groupId);
+    if (
+      proposals.length === 0 ||
+      proposals.some((p) => p.proposalType === "Add")
+    ) {
+      return;
+    }
+
+    const result = mlsClient.commitPendingProposals(groupId);
+    const epoch = mlsClient.getEpoch(groupId) + 1;
+    try {
+      await mlsApi.storeCommit(channelId, base64Encode(result.commit), epoch);
+    } catch (err) {
+      // Most likely another member committed first; their commit arrives
+      // like any other
+      mlsClient.discardPendingCommit(groupId);
+      throw err;
+    }
+    mlsClient.confirmPendingCommit(groupId);
+
+    await mlsApi.storeGroupInfo(
+      channelId,
+      base64Encode(result.groupInfo),
+      epoch,
+    );
+  },
+
   processPendingMessages: async (channelId) => {
     if (!mlsClient || !get().initialized) return;
 
@@ -309,6 +368,8 @@ export const useMlsStore = create<MlsState>((set, get) => ({
       if (processedIds.length > 0) {
         await mlsApi.ackMessages(channelId, processedIds);
       }
+
+      await get().commitPendingProposals(channelId);
     } catch (err) {
       console.error("Failed to process pending MLS messages:", err);
     }
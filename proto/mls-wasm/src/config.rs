@@ -0,0 +1,354 @@
+//! Option objects passed in from JS.
+//!
+//! Options arrive as plain JS objects with camelCase keys; `undefined` or a
+//! missing key means the openmls default unless noted otherwise.
+
+use openmls::prelude::*;
+use serde::Deserialize;
+use wasm_bindgen::{JsCast, JsValue};
+
+use crate::error::MlsError;
+
+// openmls rejects leaves whose lifetime spans more than 84 days plus the 1h
+// clock-skew margin it adds in front
+const MAX_KEY_PACKAGE_LIFETIME_SECONDS: u64 = 60 * 60 * 24 * 28 * 3;
+
+// Application messages from this many epochs back still decrypt, so a commit
+// racing a burst of chat doesn't turn the burst into errors. openmls keeps
+// none by default. The same number of past resumption secrets is kept, so a
+// group can be branched from any epoch its messages still decrypt in.
+const DEFAULT_MAX_PAST_EPOCHS: usize = 5;
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct KeyPackageOptions {
+    lifetime_seconds: Option<u64>,
+    #[serde(default)]
+    last_resort: bool,
+    capabilities: Option<CapabilityOptions>,
+    #[serde(default)]
+    extensions: Vec<ExtensionOption>,
+    #[serde(default)]
+    leaf_extensions: Vec<ExtensionOption>,
+}
+
+// Lists replace openmls's defaults; omitted lists keep them. Extension types
+// the package itself carries are always included.
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct CapabilityOptions {
+    extensions: Option<Vec<u16>>,
+    proposals: Option<Vec<u16>>,
+    credentials: Option<Vec<u16>>,
+}
+
+// An application-defined extension: type code plus opaque data
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct ExtensionOption {
+    extension_type: u16,
+    data: Vec<u8>,
+}
+
+pub fn parse<T: for<'de> Deserialize<'de> + Default + KnownKeys>(
+    value: JsValue,
+    what: &str,
+) -> Result<T, MlsError> {
+    if value.is_undefined() || value.is_null() {
+        return Ok(T::default());
+    }
+    check_keys::<T>(&value, what)?;
+    serde_wasm_bindgen::from_value(value)
+        .map_err(|e| MlsError::invalid_input(format!("invalid {}: {}", what, e)))
+}
+
+// serde-wasm-bindgen only looks up the keys a struct declares, so
+// `deny_unknown_fields` never sees a misspelt one. Each options type lists
+// its keys here and `parse` checks them, nested objects included.
+pub trait KnownKeys {
+    const KEYS: &'static [&'static str];
+
+    fn check_nested(_value: &JsValue, _what: &str) -> Result<(), MlsError> {
+        Ok(())
+    }
+}
+
+fn check_keys<T: KnownKeys>(value: &JsValue, what: &str) -> Result<(), MlsError> {
+    // Anything but a plain object is left for serde to reject
+    if !value.is_object() || js_sys::Array::is_array(value) {
+        return Ok(());
+    }
+    for key in js_sys::Object::keys(value.unchecked_ref::<js_sys::Object>()).iter() {
+        let key = key.as_string().unwrap_or_default();
+        if !T::KEYS.contains(&key.as_str()) {
+            return Err(MlsError::invalid_input(format!(
+                "invalid {}: unknown field `{}`",
+                what, key
+            )));
+        }
+    }
+    T::check_nested(value, what)
+}
+
+fn field(value: &JsValue, key: &str) -> JsValue {
+    js_sys::Reflect::get(value, &JsValue::from_str(key)).unwrap_or(JsValue::UNDEFINED)
+}
+
+fn check_each<T: KnownKeys>(list: &JsValue, what: &str) -> Result<(), MlsError> {
+    if js_sys::Array::is_array(list) {
+        for item in js_sys::Array::from(list).iter() {
+            check_keys::<T>(&item, what)?;
+        }
+    }
+    Ok(())
+}
+
+impl KnownKeys for KeyPackageOptions {
+    const KEYS: &'static [&'static str] = &[
+        "lifetimeSeconds",
+        "lastResort",
+        "capabilities",
+        "extensions",
+        "leafExtensions",
+    ];
+
+    fn check_nested(value: &JsValue, what: &str) -> Result<(), MlsError> {
+        check_keys::<CapabilityOptions>(&field(value, "capabilities"), what)?;
+        check_each::<ExtensionOption>(&field(value, "extensions"), what)?;
+        check_each::<ExtensionOption>(&field(value, "leafExtensions"), what)
+    }
+}
+
+impl KnownKeys for CapabilityOptions {
+    const KEYS: &'static [&'static str] = &["extensions", "proposals", "credentials"];
+}
+
+impl KnownKeys for ExtensionOption {
+    const KEYS: &'static [&'static str] = &["extensionType", "data"];
+}
+
+impl CapabilityOptions {
+    // `required` extension types are advertised even if the caller's list
+    // leaves them out: openmls rejects a leaf carrying an extension it doesn't
+    // claim to support
+    pub fn build(&self, required: &[ExtensionType]) -> Capabilities {
+        let mut extensions: Vec<ExtensionType> = self
+            .extensions
+            .iter()
+            .flatten()
+            .map(|&t| t.into())
+            .collect();
+        for t in required {
+            if !extensions.contains(t) {
+                extensions.push(*t);
+            }
+        }
+        let mut builder = Capabilities::builder().extensions(extensions);
+        if let Some(proposals) = &self.proposals {
+            builder = builder.proposals(proposals.iter().map(|&t| t.into()).collect());
+        }
+        if let Some(credentials) = &self.credentials {
+            builder = builder.credentials(credentials.iter().map(|&t| t.into()).collect());
+        }
+        builder.build()
+    }
+}
+
+impl ExtensionOption {
+    fn build(&self) -> Extension {
+        Extension::Unknown(self.extension_type, UnknownExtension(self.data.clone()))
+    }
+}
+
+fn invalid_extensions(what: &str) -> impl Fn(InvalidExtensionError) -> MlsError + '_ {
+    move |e| MlsError::invalid_input(format!("invalid {}: {:?}", what, e))
+}
+
+impl KeyPackageOptions {
+    pub fn builder(&self) -> Result<KeyPackageBuilder, MlsError> {
+        let mut builder = KeyPackage::builder();
+        if let Some(seconds) = self.lifetime_seconds {
+            if seconds == 0 || seconds > MAX_KEY_PACKAGE_LIFETIME_SECONDS {
+                return Err(MlsError::invalid_input(format!(
+                    "lifetime must be between 1 and {} seconds",
+                    MAX_KEY_PACKAGE_LIFETIME_SECONDS
+                )));
+            }
+            builder = builder.key_package_lifetime(Lifetime::new(seconds));
+        }
+        let mut required: Vec<ExtensionType> = self
+            .extensions
+            .iter()
+            .chain(&self.leaf_extensions)
+            .map(|e| e.extension_type.into())
+            .collect();
+        if self.last_resort {
+            builder = builder.mark_as_last_resort();
+            required.push(ExtensionType::LastResort);
+        }
+        if self.capabilities.is_some() || !required.is_empty() {
+            let defaults = CapabilityOptions::default();
+            let capabilities = self.capabilities.as_ref().unwrap_or(&defaults);
+            builder = builder.leaf_node_capabilities(capabilities.build(&required));
+        }
+        if !self.extensions.is_empty() {
+            let extensions =
+                Extensions::from_vec(self.extensions.iter().map(|e| e.build()).collect())
+                    .map_err(invalid_extensions("extensions"))?;
+            builder = builder.key_package_extensions(extensions);
+        }
+        if !self.leaf_extensions.is_empty() {
+            let extensions =
+                Extensions::from_vec(self.leaf_extensions.iter().map(|e| e.build()).collect())
+                    .map_err(invalid_extensions("leaf extensions"))?;
+            builder = builder.leaf_node_extensions(extensions);
+        }
+        Ok(builder)
+    }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct GroupOptions {
+    max_past_epochs: Option<usize>,
+    out_of_order_tolerance: Option<u32>,
+    maximum_forward_distance: Option<u32>,
+    padding_size: Option<usize>,
+    wire_format_policy: Option<WireFormat>,
+    required_capabilities: Option<CapabilityOptions>,
+}
+
+impl KnownKeys for GroupOptions {
+    const KEYS: &'static [&'static str] = &[
+        "maxPastEpochs",
+        "outOfOrderTolerance",
+        "maximumForwardDistance",
+        "paddingSize",
+        "wireFormatPolicy",
+        "requiredCapabilities",
+    ];
+
+    fn check_nested(value: &JsValue, what: &str) -> Result<(), MlsError> {
+        check_keys::<CapabilityOptions>(&field(value, "requiredCapabilities"), what)
+    }
+}
+
+// Application messages are always encrypted; the policy decides whether
+// handshake messages (proposals, commits) are sent and accepted as public
+// messages the delivery service can read
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+enum WireFormat {
+    PureCiphertext,
+    PurePlaintext,
+    MixedCiphertext,
+    MixedPlaintext,
+}
+
+impl From<WireFormat> for WireFormatPolicy {
+    fn from(format: WireFormat) -> Self {
+        match format {
+            WireFormat::PureCiphertext => PURE_CIPHERTEXT_WIRE_FORMAT_POLICY,
+            WireFormat::PurePlaintext => PURE_PLAINTEXT_WIRE_FORMAT_POLICY,
+            WireFormat::MixedCiphertext => MIXED_CIPHERTEXT_WIRE_FORMAT_POLICY,
+            WireFormat::MixedPlaintext => MIXED_PLAINTEXT_WIRE_FORMAT_POLICY,
+        }
+    }
+}
+
+impl GroupOptions {
+    fn sender_ratchet(&self) -> Option<SenderRatchetConfiguration> {
+        if self.out_of_order_tolerance.is_none() && self.maximum_forward_distance.is_none() {
+            return None;
+        }
+        let defaults = SenderRatchetConfiguration::default();
+        Some(SenderRatchetConfiguration::new(
+            self.out_of_order_tolerance
+                .unwrap_or(defaults.out_of_order_tolerance()),
+            self.maximum_forward_distance
+                .unwrap_or(defaults.maximum_forward_distance()),
+        ))
+    }
+
+    fn max_past_epochs(&self) -> usize {
+        self.max_past_epochs.unwrap_or(DEFAULT_MAX_PAST_EPOCHS)
+    }
+
+    pub fn apply(&self, mut builder: MlsGroupBuilder) -> Result<MlsGroupBuilder, MlsError> {
+        builder = builder
+            .max_past_epochs(self.max_past_epochs())
+            .number_of_resumption_psks(self.max_past_epochs());
+        if let Some(ratchet) = self.sender_ratchet() {
+            builder = builder.sender_ratchet_configuration(ratchet);
+        }
+        if let Some(size) = self.padding_size {
+            builder = builder.padding_size(size);
+        }
+        if let Some(format) = self.wire_format_policy {
+            builder = builder.with_wire_format_policy(format.into());
+        }
+        if let Some(required) = &self.required_capabilities {
+            let extensions: Vec<ExtensionType> = required
+                .extensions
+                .iter()
+                .flatten()
+                .map(|&t| t.into())
+                .collect();
+            let proposals: Vec<ProposalType> = required
+                .proposals
+                .iter()
+                .flatten()
+                .map(|&t| t.into())
+                .collect();
+            let mut credentials: Vec<CredentialType> = required
+                .credentials
+                .iter()
+                .flatten()
+                .map(|&t| t.into())
+                .collect();
+            let extension =
+                RequiredCapabilitiesExtension::new(&extensions, &proposals, &credentials);
+            let context_extensions = Extensions::single(Extension::RequiredCapabilities(extension))
+                .map_err(invalid_extensions("required capabilities"))?;
+            builder = builder.with_group_context_extensions(context_extensions);
+
+            // Our own leaf has to meet the requirements it sets
+            if !credentials.contains(&CredentialType::Basic) {
+                credentials.push(CredentialType::Basic);
+            }
+            builder = builder.with_capabilities(
+                Capabilities::builder()
+                    .extensions(extensions)
+                    .proposals(proposals)
+                    .credentials(credentials)
+                    .build(),
+            );
+        }
+        Ok(builder)
+    }
+
+    /// The settings a joining member applies to its copy of the group.
+    /// Required capabilities are part of the group context and set by the
+    /// creator, so they can't be chosen on join.
+    pub fn join_config(&self) -> Result<MlsGroupJoinConfig, MlsError> {
+        if self.required_capabilities.is_some() {
+            return Err(MlsError::invalid_input(
+                "required capabilities can only be set when creating a group",
+            ));
+        }
+        let mut builder = MlsGroupJoinConfig::builder()
+            .use_ratchet_tree_extension(true)
+            .max_past_epochs(self.max_past_epochs())
+            .number_of_resumption_psks(self.max_past_epochs());
+        if let Some(ratchet) = self.sender_ratchet() {
+            builder = builder.sender_ratchet_configuration(ratchet);
+        }
+        if let Some(size) = self.padding_size {
+            builder = builder.padding_size(size);
+        }
+        if let Some(format) = self.wire_format_policy {
+            builder = builder.wire_format_policy(format.into());
+        }
+        Ok(builder.build())
+    }
+}
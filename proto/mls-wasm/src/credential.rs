@@ -0,0 +1,290 @@
+//! DID-bound identities carried in MLS credentials.
+//!
+//! A leaf's `BasicCredential` identity is either a bare 32-byte identity key
+//! (legacy) or a TLS-encoded [`CairnIdentity`]: the identity key, the user's
+//! `did:cairn:` DID, their home server's domain, and an Ed25519 signature by
+//! the identity key over all of that plus the leaf's MLS signature key. The
+//! signature pins the DID, domain and leaf to the identity key, but nothing
+//! here ties the identity key to the DID: anyone can sign a claim to any DID
+//! with their own key. Whoever admits a leaf must resolve the DID document and
+//! check its key is the identity key (the JS credential validator's job).
+//!
+//! A user with several devices gives each one its own signing key and leaf.
+//! Device identities additionally carry a device id and a display label, and
+//! the identity key signs those too (a separate "v2" encoding, so v1
+//! identities keep decoding as before).
+
+use openmls::prelude::*;
+use openmls_rust_crypto::RustCrypto;
+use openmls_traits::crypto::OpenMlsCrypto;
+use tls_codec::{
+    Deserialize as TlsDeserializeTrait, Serialize as TlsSerializeTrait, TlsDeserialize,
+    TlsSerialize, TlsSize, VLBytes,
+};
+
+use crate::error::{ErrorCode, MlsError};
+
+const IDENTITY_KEY_LEN: usize = 32;
+const BINDING_LABEL: &[u8] = b"Cairn MLS credential v1";
+const DEVICE_BINDING_LABEL: &[u8] = b"Cairn MLS credential v2";
+const MAX_DEVICE_FIELD_LEN: usize = 64;
+
+#[derive(TlsSerialize, TlsDeserialize, TlsSize)]
+struct EncodedIdentity {
+    identity_key: VLBytes,
+    did: VLBytes,
+    home_server: VLBytes,
+    signature: VLBytes,
+}
+
+#[derive(TlsSerialize, TlsDeserialize, TlsSize)]
+struct EncodedDeviceIdentity {
+    identity_key: VLBytes,
+    did: VLBytes,
+    home_server: VLBytes,
+    device_id: VLBytes,
+    device_label: VLBytes,
+    signature: VLBytes,
+}
+
+#[derive(TlsSerialize, TlsSize)]
+struct BindingContent {
+    label: VLBytes,
+    identity_key: VLBytes,
+    did: VLBytes,
+    home_server: VLBytes,
+    signature_key: VLBytes,
+}
+
+#[derive(TlsSerialize, TlsSize)]
+struct DeviceBindingContent {
+    label: VLBytes,
+    identity_key: VLBytes,
+    did: VLBytes,
+    home_server: VLBytes,
+    device_id: VLBytes,
+    device_label: VLBytes,
+    signature_key: VLBytes,
+}
+
+pub struct CairnIdentity {
+    pub identity_key: Vec<u8>,
+    pub did: String,
+    pub home_server: String,
+    pub device: Option<Device>,
+    signature: Vec<u8>,
+}
+
+/// One of a user's devices. The id is stable and unique per user; the label
+/// is for display ("Work laptop") and may be empty.
+#[derive(Clone)]
+pub struct Device {
+    pub id: String,
+    pub label: String,
+}
+
+impl Device {
+    /// A device from optional JS arguments: no id means no device.
+    pub fn from_parts(id: Option<String>, label: Option<String>) -> Result<Option<Self>, MlsError> {
+        match (id, label) {
+            (Some(id), label) => Ok(Some(Device {
+                id,
+                label: label.unwrap_or_default(),
+            })),
+            (None, Some(_)) => Err(MlsError::invalid_input(
+                "device label given without a device id",
+            )),
+            (None, None) => Ok(None),
+        }
+    }
+}
+
+impl CairnIdentity {
+    /// Build an identity from its parts, checking the identity key's signature.
+    pub fn new(
+        identity_key: &[u8],
+        did: &str,
+        home_server: &str,
+        device: Option<Device>,
+        signing_public_key: &[u8],
+        signature: &[u8],
+    ) -> Result<Self, MlsError> {
+        let identity = CairnIdentity {
+            identity_key: identity_key.to_vec(),
+            did: did.to_string(),
+            home_server: home_server.to_string(),
+            device,
+            signature: signature.to_vec(),
+        };
+        identity.check_fields()?;
+        identity.verify(signing_public_key)?;
+        Ok(identity)
+    }
+
+    /// Decode a credential identity. `None` means a legacy bare identity key.
+    pub fn decode(identity: &[u8]) -> Result<Option<Self>, MlsError> {
+        if identity.len() == IDENTITY_KEY_LEN {
+            return Ok(None);
+        }
+        let text = |field: &VLBytes, name: &str| {
+            String::from_utf8(field.as_slice().to_vec())
+                .map_err(|_| MlsError::invalid_input(format!("{} is not valid UTF-8", name)))
+        };
+        // Exact decoding tells the two encodings apart: each leaves bytes
+        // over or runs short when read as the other
+        let identity = match EncodedIdentity::tls_deserialize_exact(identity) {
+            Ok(encoded) => CairnIdentity {
+                identity_key: encoded.identity_key.as_slice().to_vec(),
+                did: text(&encoded.did, "did")?,
+                home_server: text(&encoded.home_server, "home server")?,
+                device: None,
+                signature: encoded.signature.as_slice().to_vec(),
+            },
+            Err(_) => {
+                let encoded =
+                    EncodedDeviceIdentity::tls_deserialize_exact(identity).map_err(|e| {
+                        MlsError::invalid_input(format!(
+                            "identity is neither a 32-byte key nor a Cairn identity: {:?}",
+                            e
+                        ))
+                    })?;
+                CairnIdentity {
+                    identity_key: encoded.identity_key.as_slice().to_vec(),
+                    did: text(&encoded.did, "did")?,
+                    home_server: text(&encoded.home_server, "home server")?,
+                    device: Some(Device {
+                        id: text(&encoded.device_id, "device id")?,
+                        label: text(&encoded.device_label, "device label")?,
+                    }),
+                    signature: encoded.signature.as_slice().to_vec(),
+                }
+            }
+        };
+        identity.check_fields()?;
+        Ok(Some(identity))
+    }
+
+    pub fn encode(&self) -> Result<Vec<u8>, MlsError> {
+        match &self.device {
+            None => EncodedIdentity {
+                identity_key: self.identity_key.clone().into(),
+                did: self.did.as_bytes().into(),
+                home_server: self.home_server.as_bytes().into(),
+                signature: self.signature.clone().into(),
+            }
+            .tls_serialize_detached(),
+            Some(device) => EncodedDeviceIdentity {
+                identity_key: self.identity_key.clone().into(),
+                did: self.did.as_bytes().into(),
+                home_server: self.home_server.as_bytes().into(),
+                device_id: device.id.as_bytes().into(),
+                device_label: device.label.as_bytes().into(),
+                signature: self.signature.clone().into(),
+            }
+            .tls_serialize_detached(),
+        }
+        .map_err(|e| MlsError::internal("encode cairn identity", e))
+    }
+
+    /// Check the identity key signed this identity for `signing_public_key`.
+    /// Says nothing about whether the DID's key is the identity key.
+    pub fn verify(&self, signing_public_key: &[u8]) -> Result<(), MlsError> {
+        let payload = binding_payload(
+            &self.identity_key,
+            &self.did,
+            &self.home_server,
+            self.device.as_ref(),
+            signing_public_key,
+        )?;
+        RustCrypto::default()
+            .verify_signature(
+                SignatureScheme::ED25519,
+                &payload,
+                &self.identity_key,
+                &self.signature,
+            )
+            .map_err(|_| {
+                MlsError::new(
+                    ErrorCode::InvalidSignature,
+                    format!(
+                        "identity key did not sign this signing key for {}@{}",
+                        self.did, self.home_server
+                    ),
+                )
+            })
+    }
+
+    fn check_fields(&self) -> Result<(), MlsError> {
+        if self.identity_key.len() != IDENTITY_KEY_LEN {
+            return Err(MlsError::invalid_input("identity key must be 32 bytes"));
+        }
+        if !self.did.starts_with("did:cairn:") {
+            return Err(MlsError::invalid_input(format!(
+                "not a did:cairn DID: {}",
+                self.did
+            )));
+        }
+        if self.home_server.is_empty() || self.home_server.contains('@') {
+            return Err(MlsError::invalid_input(format!(
+                "invalid home server domain: {:?}",
+                self.home_server
+            )));
+        }
+        if let Some(device) = &self.device {
+            if device.id.is_empty() || device.id.len() > MAX_DEVICE_FIELD_LEN {
+                return Err(MlsError::invalid_input(format!(
+                    "device id must be 1 to {} bytes",
+                    MAX_DEVICE_FIELD_LEN
+                )));
+            }
+            if device.label.len() > MAX_DEVICE_FIELD_LEN {
+                return Err(MlsError::invalid_input(format!(
+                    "device label must be at most {} bytes",
+                    MAX_DEVICE_FIELD_LEN
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The bytes the identity key signs to bind a DID (and device, if any) to
+/// an MLS signing key.
+pub fn binding_payload(
+    identity_key: &[u8],
+    did: &str,
+    home_server: &str,
+    device: Option<&Device>,
+    signing_public_key: &[u8],
+) -> Result<Vec<u8>, MlsError> {
+    match device {
+        None => BindingContent {
+            label: BINDING_LABEL.into(),
+            identity_key: identity_key.into(),
+            did: did.as_bytes().into(),
+            home_server: home_server.as_bytes().into(),
+            signature_key: signing_public_key.into(),
+        }
+        .tls_serialize_detached(),
+        Some(device) => DeviceBindingContent {
+            label: DEVICE_BINDING_LABEL.into(),
+            identity_key: identity_key.into(),
+            did: did.as_bytes().into(),
+            home_server: home_server.as_bytes().into(),
+            device_id: device.id.as_bytes().into(),
+            device_label: device.label.as_bytes().into(),
+            signature_key: signing_public_key.into(),
+        }
+        .tls_serialize_detached(),
+    }
+    .map_err(|e| MlsError::internal("encode binding", e))
+}
+
+/// Validate an identity a session is about to put in its leaves: either a
+/// bare identity key or a `CairnIdentity` signed for this signing key.
+pub fn check_own_identity(identity: &[u8], signing_public_key: &[u8]) -> Result<(), MlsError> {
+    if let Some(cairn) = CairnIdentity::decode(identity)? {
+        cairn.verify(signing_public_key)?;
+    }
+    Ok(())
+}
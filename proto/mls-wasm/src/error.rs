@@ -1,5 +1,419 @@
+//! Errors surfaced to JS.
+//!
+//! Every exported function fails with an `MlsError`, which crosses the wasm
+//! boundary as a JS `Error` named `"MlsError"` carrying a stable `code`, the
+//! human-readable `message`, and `epoch` / `groupId` when they are known.
+//! Callers branch on `code`; the message is for logs only.
+
+use std::fmt::Debug;
+
+use openmls::framing::errors::{MessageDecryptionError, SecretTreeError};
+use openmls::prelude::*;
+use openmls::schedule::errors::PskError;
 use wasm_bindgen::prelude::*;
 
-pub fn to_js_error<E: std::fmt::Debug>(e: E) -> JsError {
-    JsError::new(&format!("{:?}", e))
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    InvalidInput,
+    Unsupported,
+    UnsupportedCiphersuite,
+    SessionNotFound,
+    SessionBusy,
+    GroupNotFound,
+    MemberNotFound,
+    MalformedMessage,
+    InvalidKeyPackage,
+    NoMatchingKeyPackage,
+    WrongEpoch,
+    WrongGroup,
+    InvalidSignature,
+    InvalidCredential,
+    CredentialRejected,
+    DecryptionFailed,
+    MessageTooOld,
+    MessageTooFarAhead,
+    InvalidCommit,
+    PskNotFound,
+    PendingCommit,
+    NoPendingCommit,
+    PendingProposal,
+    ProposalNotFound,
+    Evicted,
+    LeftGroup,
+    MissingRatchetTree,
+    InvalidSnapshot,
+    Storage,
+    Internal,
+}
+
+impl ErrorCode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorCode::InvalidInput => "invalid_input",
+            ErrorCode::Unsupported => "unsupported",
+            ErrorCode::UnsupportedCiphersuite => "unsupported_ciphersuite",
+            ErrorCode::SessionNotFound => "session_not_found",
+            ErrorCode::SessionBusy => "session_busy",
+            ErrorCode::GroupNotFound => "group_not_found",
+            ErrorCode::MemberNotFound => "member_not_found",
+            ErrorCode::MalformedMessage => "malformed_message",
+            ErrorCode::InvalidKeyPackage => "invalid_key_package",
+            ErrorCode::NoMatchingKeyPackage => "no_matching_key_package",
+            ErrorCode::WrongEpoch => "wrong_epoch",
+            ErrorCode::WrongGroup => "wrong_group",
+            ErrorCode::InvalidSignature => "invalid_signature",
+            ErrorCode::InvalidCredential => "invalid_credential",
+            ErrorCode::CredentialRejected => "credential_rejected",
+            ErrorCode::DecryptionFailed => "decryption_failed",
+            ErrorCode::MessageTooOld => "message_too_old",
+            ErrorCode::MessageTooFarAhead => "message_too_far_ahead",
+            ErrorCode::InvalidCommit => "invalid_commit",
+            ErrorCode::PskNotFound => "psk_not_found",
+            ErrorCode::PendingCommit => "pending_commit",
+            ErrorCode::NoPendingCommit => "no_pending_commit",
+            ErrorCode::PendingProposal => "pending_proposal",
+            ErrorCode::ProposalNotFound => "proposal_not_found",
+            ErrorCode::Evicted => "evicted",
+            ErrorCode::LeftGroup => "left_group",
+            ErrorCode::MissingRatchetTree => "missing_ratchet_tree",
+            ErrorCode::InvalidSnapshot => "invalid_snapshot",
+            ErrorCode::Storage => "storage",
+            ErrorCode::Internal => "internal",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct MlsError {
+    code: ErrorCode,
+    message: String,
+    epoch: Option<u64>,
+    group_id: Option<Vec<u8>>,
+}
+
+impl MlsError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        MlsError {
+            code,
+            message: message.into(),
+            epoch: None,
+            group_id: None,
+        }
+    }
+
+    pub fn invalid_input(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::InvalidInput, message)
+    }
+
+    /// A failure the caller can't act on: serialization, crypto backend, bugs.
+    pub fn internal(context: &str, e: impl Debug) -> Self {
+        Self::new(ErrorCode::Internal, format!("{}: {:?}", context, e))
+    }
+
+    /// Input bytes that don't parse as the expected TLS structure.
+    pub fn decode(context: &str, e: impl Debug) -> Self {
+        Self::new(ErrorCode::MalformedMessage, format!("{}: {:?}", context, e))
+    }
+
+    pub fn storage(context: &str, e: impl Debug) -> Self {
+        Self::new(ErrorCode::Storage, format!("{}: {:?}", context, e))
+    }
+
+    /// An openmls operation error, classified by its variant.
+    pub fn from_op<E: Classify + Debug>(context: &str, e: E) -> Self {
+        Self::new(e.code(), format!("{}: {:?}", context, e))
+    }
+
+    pub fn with_epoch(mut self, epoch: u64) -> Self {
+        self.epoch = Some(epoch);
+        self
+    }
+
+    pub fn with_group(mut self, group_id: &[u8]) -> Self {
+        self.group_id = Some(group_id.to_vec());
+        self
+    }
+
+    pub fn code(&self) -> ErrorCode {
+        self.code
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl From<MlsError> for JsValue {
+    fn from(e: MlsError) -> JsValue {
+        let err = js_sys::Error::new(&e.message);
+        err.set_name("MlsError");
+        let set = |key: &str, value: JsValue| {
+            // Setting a property on a fresh Error object cannot fail
+            let _ = js_sys::Reflect::set(&err, &JsValue::from_str(key), &value);
+        };
+        set("code", JsValue::from_str(e.code.as_str()));
+        if let Some(epoch) = e.epoch {
+            set("epoch", JsValue::from_f64(epoch as f64));
+        }
+        if let Some(group_id) = e.group_id {
+            set("groupId", js_sys::Uint8Array::from(&group_id[..]).into());
+        }
+        err.into()
+    }
+}
+
+/// Maps an openmls error to the code JS callers see.
+pub trait Classify {
+    fn code(&self) -> ErrorCode;
+}
+
+impl Classify for MlsGroupStateError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            MlsGroupStateError::UseAfterEviction => ErrorCode::Evicted,
+            MlsGroupStateError::PendingCommit => ErrorCode::PendingCommit,
+            MlsGroupStateError::NoPendingCommit => ErrorCode::NoPendingCommit,
+            MlsGroupStateError::PendingProposal => ErrorCode::PendingProposal,
+            MlsGroupStateError::PendingProposalNotFound => ErrorCode::ProposalNotFound,
+            MlsGroupStateError::LibraryError(_) => ErrorCode::Internal,
+        }
+    }
+}
+
+impl Classify for KeyPackageVerifyError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            KeyPackageVerifyError::InvalidSignature
+            | KeyPackageVerifyError::InvalidLeafNodeSignature => ErrorCode::InvalidSignature,
+            _ => ErrorCode::InvalidKeyPackage,
+        }
+    }
+}
+
+impl Classify for MessageDecryptionError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            MessageDecryptionError::SecretTreeError(SecretTreeError::TooDistantInThePast)
+            | MessageDecryptionError::SecretTreeError(SecretTreeError::SecretReuseError) => {
+                ErrorCode::MessageTooOld
+            }
+            MessageDecryptionError::SecretTreeError(SecretTreeError::TooDistantInTheFuture)
+            | MessageDecryptionError::GenerationOutOfBound => ErrorCode::MessageTooFarAhead,
+            MessageDecryptionError::MalformedContent => ErrorCode::MalformedMessage,
+            _ => ErrorCode::DecryptionFailed,
+        }
+    }
+}
+
+impl Classify for ValidationError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            ValidationError::WrongGroupId => ErrorCode::WrongGroup,
+            ValidationError::WrongEpoch | ValidationError::NoPastEpochData => ErrorCode::WrongEpoch,
+            ValidationError::InvalidSignature
+            | ValidationError::InvalidMembershipTag
+            | ValidationError::InvalidLeafNodeSignature => ErrorCode::InvalidSignature,
+            ValidationError::UnableToDecrypt(e) => e.code(),
+            ValidationError::CannotDecryptOwnMessage => ErrorCode::DecryptionFailed,
+            ValidationError::KeyPackageVerifyError(e) => e.code(),
+            ValidationError::LibraryError(_) => ErrorCode::Internal,
+            _ => ErrorCode::InvalidCommit,
+        }
+    }
+}
+
+impl Classify for StageCommitError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            StageCommitError::EpochMismatch => ErrorCode::WrongEpoch,
+            StageCommitError::PskError(e) => e.code(),
+            StageCommitError::LibraryError(_) => ErrorCode::Internal,
+            _ => ErrorCode::InvalidCommit,
+        }
+    }
+}
+
+impl<S> Classify for ProcessMessageError<S> {
+    fn code(&self) -> ErrorCode {
+        match self {
+            ProcessMessageError::ValidationError(e) => e.code(),
+            ProcessMessageError::GroupStateError(e) => e.code(),
+            ProcessMessageError::InvalidCommit(e) => e.code(),
+            ProcessMessageError::StorageError(_) => ErrorCode::Storage,
+            ProcessMessageError::LibraryError(_) => ErrorCode::Internal,
+            _ => ErrorCode::InvalidCommit,
+        }
+    }
+}
+
+impl<S> Classify for WelcomeError<S> {
+    fn code(&self) -> ErrorCode {
+        match self {
+            WelcomeError::NoMatchingKeyPackage
+            | WelcomeError::PrivateInitKeyNotFound
+            | WelcomeError::JoinerSecretNotFound => ErrorCode::NoMatchingKeyPackage,
+            WelcomeError::InvalidGroupInfoSignature => ErrorCode::InvalidSignature,
+            WelcomeError::MissingRatchetTree => ErrorCode::MissingRatchetTree,
+            WelcomeError::UnableToDecrypt => ErrorCode::DecryptionFailed,
+            WelcomeError::MalformedWelcomeMessage | WelcomeError::NotAWelcomeMessage => {
+                ErrorCode::MalformedMessage
+            }
+            WelcomeError::UnsupportedCapability
+            | WelcomeError::UnsupportedExtensions
+            | WelcomeError::UnsupportedMlsVersion
+            | WelcomeError::CiphersuiteMismatch => ErrorCode::Unsupported,
+            WelcomeError::Psk(e) => e.code(),
+            WelcomeError::StorageError(_) => ErrorCode::Storage,
+            _ => ErrorCode::Internal,
+        }
+    }
+}
+
+impl<S> Classify for ExternalCommitBuilderError<S> {
+    fn code(&self) -> ErrorCode {
+        match self {
+            ExternalCommitBuilderError::MissingRatchetTree => ErrorCode::MissingRatchetTree,
+            ExternalCommitBuilderError::MissingExternalPub => ErrorCode::Unsupported,
+            ExternalCommitBuilderError::UnsupportedCiphersuite => ErrorCode::UnsupportedCiphersuite,
+            ExternalCommitBuilderError::StorageError(_) => ErrorCode::Storage,
+            ExternalCommitBuilderError::InvalidProposal(e) => e.code(),
+            _ => ErrorCode::Internal,
+        }
+    }
+}
+
+// Group operations whose only caller-relevant failures are the group's
+// state (pending commit, evicted) and storage.
+macro_rules! classify_group_op {
+    ($($error:ident),* $(,)?) => {
+        $(
+            impl<S> Classify for $error<S> {
+                fn code(&self) -> ErrorCode {
+                    match self {
+                        $error::GroupStateError(e) => e.code(),
+                        $error::StorageError(_) => ErrorCode::Storage,
+                        _ => ErrorCode::Internal,
+                    }
+                }
+            }
+        )*
+    };
+}
+
+classify_group_op!(
+    ProposeAddMemberError,
+    ProposeRemoveMemberError,
+    ProposeSelfUpdateError,
+    LeaveGroupError,
+    ProposalError,
+);
+
+// Commits also take in the queued proposals, so they can fail loading a PSK
+// one of those references
+macro_rules! classify_commit_op {
+    ($($error:ident),* $(,)?) => {
+        $(
+            impl<S> Classify for $error<S> {
+                fn code(&self) -> ErrorCode {
+                    match self {
+                        $error::GroupStateError(e) => e.code(),
+                        $error::CreateCommitError(e) => e.code(),
+                        $error::StorageError(_) => ErrorCode::Storage,
+                        _ => ErrorCode::Internal,
+                    }
+                }
+            }
+        )*
+    };
+}
+
+classify_commit_op!(
+    AddMembersError,
+    RemoveMembersError,
+    SelfUpdateError,
+    CommitToPendingProposalsError,
+);
+
+impl Classify for PskError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            PskError::KeyNotFound => ErrorCode::PskNotFound,
+            PskError::Storage => ErrorCode::Storage,
+            PskError::LibraryError(_) => ErrorCode::Internal,
+            _ => ErrorCode::InvalidCommit,
+        }
+    }
+}
+
+impl Classify for CreateCommitError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            CreateCommitError::PskError(e)
+            | CreateCommitError::ProposalValidationError(ProposalValidationError::Psk(e)) => {
+                e.code()
+            }
+            // A remove naming a blank or out-of-range leaf
+            CreateCommitError::ProposalValidationError(
+                ProposalValidationError::UnknownMemberRemoval,
+            ) => ErrorCode::MemberNotFound,
+            CreateCommitError::CannotRemoveSelf => ErrorCode::InvalidInput,
+            _ => ErrorCode::Internal,
+        }
+    }
+}
+
+impl<S> Classify for CommitBuilderStageError<S> {
+    fn code(&self) -> ErrorCode {
+        match self {
+            CommitBuilderStageError::KeyStoreError(_) => ErrorCode::Storage,
+            CommitBuilderStageError::LibraryError(_) => ErrorCode::Internal,
+        }
+    }
+}
+
+impl Classify for KeyPackageNewError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            KeyPackageNewError::StorageError => ErrorCode::Storage,
+            KeyPackageNewError::LibraryError(_) => ErrorCode::Internal,
+            _ => ErrorCode::InvalidInput,
+        }
+    }
+}
+
+impl Classify for CreateMessageError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            CreateMessageError::GroupStateError(e) => e.code(),
+            CreateMessageError::LibraryError(_) => ErrorCode::Internal,
+        }
+    }
+}
+
+impl Classify for ExportSecretError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            ExportSecretError::GroupStateError(e) => e.code(),
+            ExportSecretError::KeyLengthTooLong => ErrorCode::InvalidInput,
+            ExportSecretError::LibraryError(_) => ErrorCode::Internal,
+        }
+    }
+}
+
+impl Classify for ExportGroupInfoError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            ExportGroupInfoError::GroupStateError(e) => e.code(),
+            _ => ErrorCode::Internal,
+        }
+    }
+}
+
+impl<S> Classify for MergePendingCommitError<S> {
+    fn code(&self) -> ErrorCode {
+        match self {
+            MergePendingCommitError::MlsGroupStateError(e) => e.code(),
+            MergePendingCommitError::MergeCommitError(_) => ErrorCode::Internal,
+        }
+    }
 }
@@ -0,0 +1,77 @@
+//! Human-comparable codes for a group's state.
+//!
+//! Members in the same epoch of the same group share its epoch authenticator;
+//! a server that forked the group cannot make two branches agree on it. The
+//! fingerprint hashes the authenticator down to something two people can read
+//! out or scan and compare: six blocks of five digits, or eight emoji from
+//! the 64-emoji table used for Matrix SAS verification.
+
+use openmls_rust_crypto::RustCrypto;
+use openmls_traits::crypto::OpenMlsCrypto;
+use openmls_traits::types::HashType;
+
+use crate::error::MlsError;
+
+const FINGERPRINT_LABEL: &[u8] = b"Cairn group fingerprint v1";
+const NUMERIC_BLOCKS: usize = 6;
+const NUMERIC_BLOCK_BYTES: usize = 5;
+const EMOJI_COUNT: usize = 8;
+
+const EMOJI: [&str; 64] = [
+    "🐶", "🐱", "🦁", "🐎", "🦄", "🐷", "🐘", "🐰", "🐼", "🐓", "🐧", "🐢", "🐟", "🐙", "🦋", "🌷",
+    "🌳", "🌵", "🍄", "🌏", "🌙", "☁️", "🔥", "🍌", "🍎", "🍓", "🌽", "🍕", "🎂", "❤️", "😀", "🤖",
+    "🎩", "👓", "🔧", "🎅", "👍", "☂️", "⌛", "⏰", "🎁", "💡", "📕", "✏️", "📎", "✂️", "🔒", "🔑",
+    "🔨", "☎️", "🏁", "🚂", "🚲", "✈️", "🚀", "🏆", "⚽", "🎸", "🎺", "🔔", "⚓", "🎧", "📁", "📌",
+];
+
+pub enum Format {
+    Numeric,
+    Emoji,
+}
+
+impl Format {
+    pub fn parse(format: Option<&str>) -> Result<Self, MlsError> {
+        match format {
+            None | Some("numeric") => Ok(Format::Numeric),
+            Some("emoji") => Ok(Format::Emoji),
+            Some(other) => Err(MlsError::invalid_input(format!(
+                "unknown fingerprint format {:?}; expected \"numeric\" or \"emoji\"",
+                other
+            ))),
+        }
+    }
+}
+
+pub fn fingerprint(epoch_authenticator: &[u8], format: Format) -> Result<String, MlsError> {
+    if epoch_authenticator.is_empty() {
+        return Err(MlsError::invalid_input("epoch authenticator is empty"));
+    }
+    let digest = RustCrypto::default()
+        .hash(
+            HashType::Sha2_256,
+            &[FINGERPRINT_LABEL, epoch_authenticator].concat(),
+        )
+        .map_err(|e| MlsError::internal("hash epoch authenticator", e))?;
+
+    let parts: Vec<String> = match format {
+        // 40 bits per block, reduced to five digits
+        Format::Numeric => digest
+            .chunks(NUMERIC_BLOCK_BYTES)
+            .take(NUMERIC_BLOCKS)
+            .map(|chunk| {
+                let value = chunk.iter().fold(0u64, |acc, &b| (acc << 8) | u64::from(b));
+                format!("{:05}", value % 100_000)
+            })
+            .collect(),
+        // Six bits per emoji, read from the front of the digest
+        Format::Emoji => (0..EMOJI_COUNT)
+            .map(|i| {
+                let bit = i * 6;
+                let pair = (u16::from(digest[bit / 8]) << 8) | u16::from(digest[bit / 8 + 1]);
+                let index = (pair >> (10 - bit % 8)) & 0x3f;
+                EMOJI[usize::from(index)].to_string()
+            })
+            .collect(),
+    };
+    Ok(parts.join(" "))
+}
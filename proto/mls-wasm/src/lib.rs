@@ -1,16 +1,49 @@
+mod config;
+mod credential;
 mod error;
+mod fingerprint;
+mod persistence;
+mod psk;
 mod session;
+mod storage;
 
+use openmls::ciphersuite::hash_ref::ProposalRef;
+use openmls::messages::group_info::GroupInfo;
 use openmls::prelude::*;
+use openmls::schedule::Psk;
 use openmls_basic_credential::SignatureKeyPair;
-use openmls_rust_crypto::OpenMlsRustCrypto;
+use openmls_rust_crypto::{OpenMlsRustCrypto, RustCrypto};
+use openmls_traits::storage::StorageProvider;
 use tls_codec::Deserialize as TlsDeserializeTrait;
 use tls_codec::Serialize as TlsSerializeTrait;
 use wasm_bindgen::prelude::*;
 
-use error::to_js_error;
+use credential::{CairnIdentity, Device};
+use error::{ErrorCode, MlsError};
+use storage::{
+    JsStorage, SessionStorage, LAST_SELF_UPDATE_LABEL, LEFT_GROUP_LABEL, USED_KEY_PACKAGES_LABEL,
+};
 
-const CIPHERSUITE: Ciphersuite = Ciphersuite::MLS_128_DHKEMX25519_AES128GCM_SHA256_Ed25519;
+const DEFAULT_CIPHERSUITE: Ciphersuite = Ciphersuite::MLS_128_DHKEMX25519_AES128GCM_SHA256_Ed25519;
+
+fn resolve_ciphersuite(value: Option<u16>) -> Result<Ciphersuite, MlsError> {
+    let Some(value) = value else {
+        return Ok(DEFAULT_CIPHERSUITE);
+    };
+    let ciphersuite = Ciphersuite::try_from(value).map_err(|_| {
+        MlsError::new(
+            ErrorCode::UnsupportedCiphersuite,
+            format!("unknown ciphersuite {:#06x}", value),
+        )
+    })?;
+    RustCrypto::default().supports(ciphersuite).map_err(|_| {
+        MlsError::new(
+            ErrorCode::UnsupportedCiphersuite,
+            format!("ciphersuite {:?} is not supported", ciphersuite),
+        )
+    })?;
+    Ok(ciphersuite)
+}
 
 // ==================== Smoke / Info ====================
 
@@ -21,13 +54,18 @@ pub fn mls_version() -> String {
 
 #[wasm_bindgen]
 pub fn supported_ciphersuites() -> String {
-    serde_json::json!([
-        {
-            "name": "MLS_128_DHKEMX25519_AES128GCM_SHA256_Ed25519",
-            "value": 1
-        }
-    ])
-    .to_string()
+    let suites: Vec<serde_json::Value> = RustCrypto::default()
+        .supported_ciphersuites()
+        .into_iter()
+        .map(|cs| {
+            serde_json::json!({
+                "name": format!("{:?}", cs),
+                "value": u16::from(cs),
+                "default": cs == DEFAULT_CIPHERSUITE,
+            })
+        })
+        .collect();
+    serde_json::Value::from(suites).to_string()
 }
 
 // ==================== Credential Bundle ====================
@@ -37,6 +75,7 @@ pub struct WasmCredentialBundle {
     identity: Vec<u8>,
     signing_public_key: Vec<u8>,
     signing_private_key: Vec<u8>,
+    ciphersuite: u16,
 }
 
 #[wasm_bindgen]
@@ -55,30 +94,96 @@ impl WasmCredentialBundle {
     pub fn signing_private_key(&self) -> Vec<u8> {
         self.signing_private_key.clone()
     }
+
+    #[wasm_bindgen(getter)]
+    pub fn ciphersuite(&self) -> u16 {
+        self.ciphersuite
+    }
+}
+
+/// The bytes the identity key must sign (Ed25519) to bind `did` and
+/// `home_server` to an MLS signing key. Pass the signature to
+/// `create_cairn_identity`. With a `device_id`, the device id and label are
+/// bound too, so members can tell a user's devices apart.
+#[wasm_bindgen]
+pub fn cairn_identity_payload(
+    identity_public_key: &[u8],
+    did: &str,
+    home_server: &str,
+    signing_public_key: &[u8],
+    device_id: Option<String>,
+    device_label: Option<String>,
+) -> Result<Vec<u8>, MlsError> {
+    let device = Device::from_parts(device_id, device_label)?;
+    credential::binding_payload(
+        identity_public_key,
+        did,
+        home_server,
+        device.as_ref(),
+        signing_public_key,
+    )
+}
+
+/// Encode a DID-bound identity after checking the identity key's signature.
+/// Use the result in place of the bare identity key when creating a session
+/// or key packages. `device_id` and `device_label` must match what was
+/// passed to `cairn_identity_payload`.
+#[wasm_bindgen]
+pub fn create_cairn_identity(
+    identity_public_key: &[u8],
+    did: &str,
+    home_server: &str,
+    signing_public_key: &[u8],
+    signature: &[u8],
+    device_id: Option<String>,
+    device_label: Option<String>,
+) -> Result<Vec<u8>, MlsError> {
+    CairnIdentity::new(
+        identity_public_key,
+        did,
+        home_server,
+        Device::from_parts(device_id, device_label)?,
+        signing_public_key,
+        signature,
+    )?
+    .encode()
 }
 
 #[wasm_bindgen]
-pub fn create_credential(identity_public_key: &[u8]) -> Result<WasmCredentialBundle, JsError> {
+pub fn create_credential(
+    identity_public_key: &[u8],
+    ciphersuite: Option<u16>,
+) -> Result<WasmCredentialBundle, MlsError> {
     if identity_public_key.len() != 32 {
-        return Err(JsError::new("identity public key must be 32 bytes"));
+        return Err(MlsError::invalid_input(
+            "identity public key must be 32 bytes",
+        ));
     }
+    let ciphersuite = resolve_ciphersuite(ciphersuite)?;
 
-    let keys = SignatureKeyPair::new(CIPHERSUITE.signature_algorithm()).map_err(to_js_error)?;
+    let keys = SignatureKeyPair::new(ciphersuite.signature_algorithm())
+        .map_err(|e| MlsError::internal("generate signature key", e))?;
 
-    let kp_value = serde_json::to_value(&keys)
-        .map_err(|e| JsError::new(&format!("serialize keypair: {}", e)))?;
+    let kp_value =
+        serde_json::to_value(&keys).map_err(|e| MlsError::internal("serialize keypair", e))?;
     let private_bytes: Vec<u8> = serde_json::from_value(
         kp_value
             .get("private")
-            .ok_or_else(|| JsError::new("missing private key in serialized keypair"))?
+            .ok_or_else(|| {
+                MlsError::new(
+                    ErrorCode::Internal,
+                    "missing private key in serialized keypair",
+                )
+            })?
             .clone(),
     )
-    .map_err(|e| JsError::new(&format!("extract private key: {}", e)))?;
+    .map_err(|e| MlsError::internal("extract private key", e))?;
 
     Ok(WasmCredentialBundle {
         identity: identity_public_key.to_vec(),
         signing_public_key: keys.public().to_vec(),
         signing_private_key: private_bytes,
+        ciphersuite: ciphersuite.into(),
     })
 }
 
@@ -87,25 +192,26 @@ pub fn import_signing_key(
     identity_public_key: &[u8],
     signing_private_key: &[u8],
     signing_public_key: &[u8],
-) -> Result<WasmCredentialBundle, JsError> {
+    ciphersuite: Option<u16>,
+) -> Result<WasmCredentialBundle, MlsError> {
     if identity_public_key.len() != 32 {
-        return Err(JsError::new("identity public key must be 32 bytes"));
-    }
-    if signing_public_key.len() != 32 {
-        return Err(JsError::new("signing public key must be 32 bytes"));
+        return Err(MlsError::invalid_input(
+            "identity public key must be 32 bytes",
+        ));
     }
+    let ciphersuite = resolve_ciphersuite(ciphersuite)?;
 
-    let private_key = normalize_signing_key(signing_private_key)?;
-    let _keys = SignatureKeyPair::from_raw(
-        CIPHERSUITE.signature_algorithm(),
-        private_key.clone(),
-        signing_public_key.to_vec(),
-    );
+    let private_key = normalize_signing_key(
+        ciphersuite.signature_algorithm(),
+        signing_private_key,
+        signing_public_key,
+    )?;
 
     Ok(WasmCredentialBundle {
         identity: identity_public_key.to_vec(),
         signing_public_key: signing_public_key.to_vec(),
         signing_private_key: private_key,
+        ciphersuite: ciphersuite.into(),
     })
 }
 
@@ -130,13 +236,43 @@ impl WasmKeyPackageResult {
     }
 }
 
-fn normalize_signing_key(signing_private_key: &[u8]) -> Result<Vec<u8>, JsError> {
-    match signing_private_key.len() {
-        32 => Ok(signing_private_key.to_vec()),
-        64 => Ok(signing_private_key[..32].to_vec()),
-        n => Err(JsError::new(&format!(
-            "signing private key must be 32 or 64 bytes, got {}",
-            n
+fn normalize_signing_key(
+    scheme: SignatureScheme,
+    signing_private_key: &[u8],
+    signing_public_key: &[u8],
+) -> Result<Vec<u8>, MlsError> {
+    match scheme {
+        SignatureScheme::ED25519 => {
+            if signing_public_key.len() != 32 {
+                return Err(MlsError::invalid_input(
+                    "signing public key must be 32 bytes",
+                ));
+            }
+            match signing_private_key.len() {
+                32 => Ok(signing_private_key.to_vec()),
+                64 => Ok(signing_private_key[..32].to_vec()),
+                n => Err(MlsError::invalid_input(format!(
+                    "signing private key must be 32 or 64 bytes, got {}",
+                    n
+                ))),
+            }
+        }
+        SignatureScheme::ECDSA_SECP256R1_SHA256 => {
+            if signing_public_key.len() != 65 {
+                return Err(MlsError::invalid_input(
+                    "signing public key must be a 65-byte uncompressed P-256 point",
+                ));
+            }
+            if signing_private_key.len() != 32 {
+                return Err(MlsError::invalid_input(
+                    "signing private key must be 32 bytes",
+                ));
+            }
+            Ok(signing_private_key.to_vec())
+        }
+        other => Err(MlsError::invalid_input(format!(
+            "unsupported signature scheme {:?}",
+            other
         ))),
     }
 }
@@ -146,25 +282,28 @@ pub fn generate_key_package(
     identity: &[u8],
     signing_private_key: &[u8],
     signing_public_key: &[u8],
-) -> Result<WasmKeyPackageResult, JsError> {
-    if identity.len() != 32 {
-        return Err(JsError::new("identity must be 32 bytes"));
-    }
-    if signing_public_key.len() != 32 {
-        return Err(JsError::new("signing public key must be 32 bytes"));
-    }
-
-    let private_key = normalize_signing_key(signing_private_key)?;
+    ciphersuite: Option<u16>,
+    options: JsValue,
+) -> Result<WasmKeyPackageResult, MlsError> {
+    credential::check_own_identity(identity, signing_public_key)?;
+    let ciphersuite = resolve_ciphersuite(ciphersuite)?;
+    let options: config::KeyPackageOptions = config::parse(options, "key package options")?;
+
+    let private_key = normalize_signing_key(
+        ciphersuite.signature_algorithm(),
+        signing_private_key,
+        signing_public_key,
+    )?;
     let provider = OpenMlsRustCrypto::default();
 
     let signer = SignatureKeyPair::from_raw(
-        CIPHERSUITE.signature_algorithm(),
+        ciphersuite.signature_algorithm(),
         private_key,
         signing_public_key.to_vec(),
     );
     signer
         .store(provider.storage())
-        .map_err(|_| JsError::new("failed to store signer"))?;
+        .map_err(|e| MlsError::storage("store signer", e))?;
 
     let credential = BasicCredential::new(identity.to_vec());
     let credential_with_key = CredentialWithKey {
@@ -172,24 +311,116 @@ pub fn generate_key_package(
         signature_key: SignaturePublicKey::from(signing_public_key.to_vec()),
     };
 
-    let bundle = KeyPackage::builder()
-        .build(CIPHERSUITE, &provider, &signer, credential_with_key)
-        .map_err(to_js_error)?;
+    let bundle = options
+        .builder()?
+        .build(ciphersuite, &provider, &signer, credential_with_key)
+        .map_err(|e| MlsError::from_op("build key package", e))?;
+
+    key_package_result(&bundle)
+}
+
+// Extensions as `{extension_type, name, data}`; `data` is only given for
+// application-defined extensions, whose contents openmls doesn't parse
+fn extensions_json<'a>(extensions: impl Iterator<Item = &'a Extension>) -> serde_json::Value {
+    extensions
+        .map(|e| {
+            let data = match e {
+                Extension::Unknown(_, UnknownExtension(data)) => Some(data),
+                _ => None,
+            };
+            serde_json::json!({
+                "extension_type": u16::from(e.extension_type()),
+                "name": format!("{:?}", e.extension_type()),
+                "data": data,
+            })
+        })
+        .collect()
+}
+
+/// Validate a KeyPackage without a session and describe it as JSON:
+/// `{ciphersuite, identity, did, home_server, device_id, device_label,
+/// signature_key, lifetime:
+/// {not_before, not_after}, last_resort, capabilities: {ciphersuites,
+/// extensions, proposals, credentials}, extensions, leaf_extensions,
+/// hash_ref}`. Fails if either signature is invalid, the package has
+/// expired, or a DID-bound identity's binding doesn't verify.
+#[wasm_bindgen]
+pub fn inspect_key_package(key_package_tls: &[u8]) -> Result<String, MlsError> {
+    let crypto = RustCrypto::default();
+    let kp_in = KeyPackageIn::tls_deserialize_exact(key_package_tls)
+        .map_err(|e| MlsError::decode("deserialize key package", e))?;
+    let kp = kp_in
+        .validate(&crypto, ProtocolVersion::Mls10)
+        .map_err(|e| MlsError::from_op("validate key package", e))?;
+
+    let leaf_node = kp.leaf_node();
+    let cairn = cairn_identity(leaf_node.credential())?;
+    if let Some(cairn) = &cairn {
+        cairn.verify(leaf_node.signature_key().as_slice())?;
+    }
+    let hash_ref = kp
+        .hash_ref(&crypto)
+        .map_err(|e| MlsError::internal("hash key package", e))?;
+    let lifetime = kp.life_time();
+    let capabilities = leaf_node.capabilities();
+
+    let info = serde_json::json!({
+        "ciphersuite": u16::from(kp.ciphersuite()),
+        "identity": extract_identity(leaf_node.credential()),
+        "did": cairn.as_ref().map(|c| &c.did),
+        "home_server": cairn.as_ref().map(|c| &c.home_server),
+        "device_id": cairn.as_ref().and_then(|c| c.device.as_ref()).map(|d| &d.id),
+        "device_label": cairn.as_ref().and_then(|c| c.device.as_ref()).map(|d| &d.label),
+        "signature_key": leaf_node.signature_key().as_slice(),
+        "lifetime": {
+            "not_before": lifetime.not_before(),
+            "not_after": lifetime.not_after(),
+        },
+        "last_resort": kp.last_resort(),
+        "capabilities": {
+            "ciphersuites": capabilities
+                .ciphersuites()
+                .iter()
+                .map(|c| c.value())
+                .collect::<Vec<_>>(),
+            "extensions": capabilities
+                .extensions()
+                .iter()
+                .map(|&t| u16::from(t))
+                .collect::<Vec<_>>(),
+            "proposals": capabilities
+                .proposals()
+                .iter()
+                .map(|&t| u16::from(t))
+                .collect::<Vec<_>>(),
+            "credentials": capabilities
+                .credentials()
+                .iter()
+                .map(|&t| u16::from(t))
+                .collect::<Vec<_>>(),
+        },
+        "extensions": extensions_json(kp.extensions().iter()),
+        "leaf_extensions": extensions_json(leaf_node.extensions().iter()),
+        "hash_ref": hash_ref.as_slice(),
+    });
+    Ok(info.to_string())
+}
 
+fn key_package_result(bundle: &KeyPackageBundle) -> Result<WasmKeyPackageResult, MlsError> {
     let kp_bytes = bundle
         .key_package()
         .tls_serialize_detached()
-        .map_err(to_js_error)?;
+        .map_err(|e| MlsError::internal("serialize key package", e))?;
 
     let init_key_value = serde_json::to_value(bundle.init_private_key())
-        .map_err(|e| JsError::new(&format!("serialize init key: {}", e)))?;
+        .map_err(|e| MlsError::internal("serialize init key", e))?;
     let init_key_bytes: Vec<u8> = serde_json::from_value(
         init_key_value
             .get("vec")
-            .ok_or_else(|| JsError::new("missing vec in init key"))?
+            .ok_or_else(|| MlsError::new(ErrorCode::Internal, "missing vec in init key"))?
             .clone(),
     )
-    .map_err(|e| JsError::new(&format!("extract init key bytes: {}", e)))?;
+    .map_err(|e| MlsError::internal("extract init key bytes", e))?;
 
     Ok(WasmKeyPackageResult {
         key_package_data: kp_bytes,
@@ -203,17 +434,17 @@ fn build_signer_and_credential(
     identity: &[u8],
     signing_private_key: &[u8],
     signing_public_key: &[u8],
-) -> Result<(SignatureKeyPair, CredentialWithKey), JsError> {
-    if identity.len() != 32 {
-        return Err(JsError::new("identity must be 32 bytes"));
-    }
-    if signing_public_key.len() != 32 {
-        return Err(JsError::new("signing public key must be 32 bytes"));
-    }
-
-    let private_key = normalize_signing_key(signing_private_key)?;
+    ciphersuite: Ciphersuite,
+) -> Result<(SignatureKeyPair, CredentialWithKey), MlsError> {
+    credential::check_own_identity(identity, signing_public_key)?;
+
+    let private_key = normalize_signing_key(
+        ciphersuite.signature_algorithm(),
+        signing_private_key,
+        signing_public_key,
+    )?;
     let signer = SignatureKeyPair::from_raw(
-        CIPHERSUITE.signature_algorithm(),
+        ciphersuite.signature_algorithm(),
         private_key,
         signing_public_key.to_vec(),
     );
@@ -232,51 +463,270 @@ pub fn create_session(
     identity: &[u8],
     signing_private_key: &[u8],
     signing_public_key: &[u8],
-) -> Result<u32, JsError> {
-    let (signer, credential_with_key) =
-        build_signer_and_credential(identity, signing_private_key, signing_public_key)?;
-    session::new_session(identity.to_vec(), signer, credential_with_key)
-        .map_err(|e| JsError::new(&e))
+    storage: Option<JsStorage>,
+    ciphersuite: Option<u16>,
+) -> Result<u32, MlsError> {
+    let ciphersuite = resolve_ciphersuite(ciphersuite)?;
+    let (signer, credential_with_key) = build_signer_and_credential(
+        identity,
+        signing_private_key,
+        signing_public_key,
+        ciphersuite,
+    )?;
+    let storage = match storage {
+        Some(js) => SessionStorage::with_js(js),
+        None => SessionStorage::in_memory(),
+    };
+    session::new_session(
+        identity.to_vec(),
+        signer,
+        credential_with_key,
+        ciphersuite,
+        storage,
+    )
 }
 
 #[wasm_bindgen]
-pub fn destroy_session(session_id: u32) -> bool {
+pub fn destroy_session(session_id: u32) -> Result<bool, MlsError> {
     session::drop_session(session_id)
 }
 
 #[wasm_bindgen]
-pub fn session_generate_key_package(session_id: u32) -> Result<WasmKeyPackageResult, JsError> {
+pub fn export_session(session_id: u32, key: &[u8]) -> Result<Vec<u8>, MlsError> {
+    session::with_session(session_id, |s| persistence::export_session(s, key))
+}
+
+#[wasm_bindgen]
+pub fn import_session(blob: &[u8], key: &[u8]) -> Result<u32, MlsError> {
+    let restored = persistence::import_session(blob, key)?;
+    session::insert_session(restored)
+}
+
+#[wasm_bindgen]
+pub fn session_generate_key_package(
+    session_id: u32,
+    options: JsValue,
+) -> Result<WasmKeyPackageResult, MlsError> {
+    let options: config::KeyPackageOptions = config::parse(options, "key package options")?;
     session::with_session(session_id, |s| {
-        let bundle = KeyPackage::builder()
+        // A last-resort package survives the Welcomes that use it, so it keeps
+        // the user reachable once the server's pool of one-shot packages is empty
+        let bundle = options
+            .builder()?
             .build(
-                CIPHERSUITE,
+                s.ciphersuite,
                 &s.provider,
                 &s.signer,
                 s.credential_with_key.clone(),
             )
-            .map_err(|e| format!("{:?}", e))?;
+            .map_err(|e| MlsError::from_op("build key package", e))?;
 
-        let kp_bytes = bundle
-            .key_package()
-            .tls_serialize_detached()
-            .map_err(|e| format!("{:?}", e))?;
-
-        let init_key_value = serde_json::to_value(bundle.init_private_key())
-            .map_err(|e| format!("serialize init key: {}", e))?;
-        let init_key_bytes: Vec<u8> = serde_json::from_value(
-            init_key_value
-                .get("vec")
-                .ok_or_else(|| "missing vec in init key".to_string())?
-                .clone(),
-        )
-        .map_err(|e| format!("extract init key: {}", e))?;
+        key_package_result(&bundle)
+    })
+}
 
-        Ok(WasmKeyPackageResult {
-            key_package_data: kp_bytes,
-            init_private_key: init_key_bytes,
-        })
+/// Generate a pool of KeyPackages for upload. Unlike
+/// `session_generate_key_package`, the init private keys never leave the
+/// session. Returns JSON `[{key_package, hash_ref}]`; `take_used_key_packages`
+/// later reports packages by the same hash ref.
+#[wasm_bindgen]
+pub fn generate_key_packages(
+    session_id: u32,
+    count: u32,
+    options: JsValue,
+) -> Result<String, MlsError> {
+    let options: config::KeyPackageOptions = config::parse(options, "key package options")?;
+    session::with_session(session_id, |s| {
+        let mut packages = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let bundle = options
+                .builder()?
+                .build(
+                    s.ciphersuite,
+                    &s.provider,
+                    &s.signer,
+                    s.credential_with_key.clone(),
+                )
+                .map_err(|e| MlsError::from_op("build key package", e))?;
+            let key_package = bundle.key_package();
+            let hash_ref = key_package
+                .hash_ref(s.provider.crypto())
+                .map_err(|e| MlsError::internal("hash key package", e))?;
+            let tls = key_package
+                .tls_serialize_detached()
+                .map_err(|e| MlsError::internal("serialize key package", e))?;
+            packages.push(serde_json::json!({
+                "key_package": tls,
+                "hash_ref": hash_ref.as_slice(),
+            }));
+        }
+        serde_json::to_string(&packages)
+            .map_err(|e| MlsError::internal("serialize key packages", e))
+    })
+}
+
+/// Hash refs of the session's KeyPackages consumed by Welcomes since the last
+/// call, as a JSON array; the list is cleared. Upload that many replacements.
+/// Last-resort packages stay valid and are never reported.
+#[wasm_bindgen]
+pub fn take_used_key_packages(session_id: u32) -> Result<String, MlsError> {
+    session::with_session(session_id, |s| {
+        let storage = s.provider.storage();
+        let used: Vec<Vec<u8>> = storage
+            .read_meta(USED_KEY_PACKAGES_LABEL, &[])
+            .map_err(|e| MlsError::storage("read used key packages", e))?
+            .unwrap_or_default();
+        storage
+            .write_meta(USED_KEY_PACKAGES_LABEL, &[], &Vec::<Vec<u8>>::new())
+            .map_err(|e| MlsError::storage("clear used key packages", e))?;
+        serde_json::to_string(&used)
+            .map_err(|e| MlsError::internal("serialize used key packages", e))
+    })
+}
+
+// The consumable KeyPackage of ours a Welcome was encrypted to, if any.
+// Must be looked up before staging: openmls deletes the package then
+// (unless it is a last-resort package, which stays usable).
+fn welcome_key_package(
+    s: &session::Session,
+    welcome: &Welcome,
+) -> Result<Option<KeyPackageRef>, MlsError> {
+    for secrets in welcome.secrets() {
+        let hash_ref = secrets.new_member();
+        let bundle: Option<KeyPackageBundle> = s
+            .provider
+            .storage()
+            .key_package(&hash_ref)
+            .map_err(|e| MlsError::storage("read key package", e))?;
+        if let Some(bundle) = bundle {
+            return Ok((!bundle.key_package().last_resort()).then_some(hash_ref));
+        }
+    }
+    Ok(None)
+}
+
+fn record_used_key_package(s: &session::Session, hash_ref: &KeyPackageRef) -> Result<(), MlsError> {
+    let storage = s.provider.storage();
+    let mut used: Vec<Vec<u8>> = storage
+        .read_meta(USED_KEY_PACKAGES_LABEL, &[])
+        .map_err(|e| MlsError::storage("read used key packages", e))?
+        .unwrap_or_default();
+    used.push(hash_ref.as_slice().to_vec());
+    storage
+        .write_meta(USED_KEY_PACKAGES_LABEL, &[], &used)
+        .map_err(|e| MlsError::storage("record used key package", e))
+}
+
+// ==================== Credential Validation ====================
+
+/// Register (or clear, with `undefined`) a callback that vets every credential
+/// entering or changing in a group: members of a group being joined (by
+/// Welcome or external commit), added key packages, update proposals and
+/// commit update paths. It receives
+/// `{ identity, signatureKey, credential }` (credential is TLS-serialized),
+/// plus `did` and `homeServer` for DID-bound credentials, whose binding
+/// signature has already been verified. That signature only shows the
+/// identity key claimed the DID, so for DID-bound credentials the callback
+/// must resolve the DID document and compare its key to `identity`. It must
+/// return `true` to accept; returning anything else or throwing refuses the
+/// whole operation with `credential_rejected`.
+///
+/// The callback runs synchronously inside the MLS call. Any session function
+/// it calls, on this session or another, fails with `session_busy`.
+#[wasm_bindgen]
+pub fn set_credential_validator(
+    session_id: u32,
+    validator: Option<js_sys::Function>,
+) -> Result<(), MlsError> {
+    session::with_session(session_id, |s| {
+        s.credential_validator = validator;
+        Ok(())
     })
-    .map_err(|e| JsError::new(&e))
+}
+
+fn check_credential(
+    s: &session::Session,
+    credential: &Credential,
+    signature_key: &SignaturePublicKey,
+) -> Result<(), MlsError> {
+    let rejected = |reason: String| {
+        MlsError::new(
+            ErrorCode::CredentialRejected,
+            format!("credential rejected: {}", reason),
+        )
+    };
+
+    // A DID binding is checked whether or not JS registered a validator: a
+    // leaf claiming a DID its identity key never signed for is never admitted
+    let cairn = cairn_identity(credential)?;
+    if let Some(cairn) = &cairn {
+        cairn
+            .verify(signature_key.as_slice())
+            .map_err(|e| rejected(e.message().to_string()))?;
+    }
+
+    let Some(validator) = &s.credential_validator else {
+        return Ok(());
+    };
+    let identity = extract_identity(credential);
+    let credential_tls = credential
+        .tls_serialize_detached()
+        .map_err(|e| MlsError::internal("serialize credential", e))?;
+
+    let info = js_sys::Object::new();
+    let set = |key: &str, value: &[u8]| {
+        // Setting a property on a fresh object cannot fail
+        let _ = js_sys::Reflect::set(
+            &info,
+            &JsValue::from_str(key),
+            &js_sys::Uint8Array::from(value).into(),
+        );
+    };
+    set("identity", &identity);
+    set("signatureKey", signature_key.as_slice());
+    set("credential", &credential_tls);
+    if let Some(cairn) = cairn {
+        let _ = js_sys::Reflect::set(&info, &"did".into(), &cairn.did.into());
+        let _ = js_sys::Reflect::set(&info, &"homeServer".into(), &cairn.home_server.into());
+        if let Some(device) = cairn.device {
+            let _ = js_sys::Reflect::set(&info, &"deviceId".into(), &device.id.into());
+            let _ = js_sys::Reflect::set(&info, &"deviceLabel".into(), &device.label.into());
+        }
+    }
+
+    match validator.call1(&JsValue::NULL, &info) {
+        Ok(verdict) if verdict == JsValue::TRUE => Ok(()),
+        Ok(_) => Err(rejected("validator returned false".to_string())),
+        Err(thrown) => Err(rejected(
+            thrown
+                .dyn_ref::<js_sys::Error>()
+                .map(|e| String::from(e.message()))
+                .unwrap_or_else(|| format!("{:?}", thrown)),
+        )),
+    }
+}
+
+fn check_leaf_node(s: &session::Session, leaf_node: &LeafNode) -> Result<(), MlsError> {
+    check_credential(s, leaf_node.credential(), leaf_node.signature_key())
+}
+
+// Credentials a proposal would bring into the group
+fn check_proposal(s: &session::Session, proposal: &Proposal) -> Result<(), MlsError> {
+    match proposal {
+        Proposal::Add(add) => check_leaf_node(s, add.key_package().leaf_node()),
+        Proposal::Update(update) => check_leaf_node(s, update.leaf_node()),
+        _ => Ok(()),
+    }
+}
+
+fn check_staged_commit(s: &session::Session, staged: &StagedCommit) -> Result<(), MlsError> {
+    if let Some(leaf_node) = staged.update_path_leaf_node() {
+        check_leaf_node(s, leaf_node)?;
+    }
+    for queued in staged.queued_proposals() {
+        check_proposal(s, queued.proposal())?;
+    }
+    Ok(())
 }
 
 // ==================== Group Operations ====================
@@ -285,6 +735,7 @@ pub fn session_generate_key_package(session_id: u32) -> Result<WasmKeyPackageRes
 pub struct WasmAddMemberResult {
     commit: Vec<u8>,
     welcome: Vec<u8>,
+    group_info: Vec<u8>,
 }
 
 #[wasm_bindgen]
@@ -298,6 +749,112 @@ impl WasmAddMemberResult {
     pub fn welcome(&self) -> Vec<u8> {
         self.welcome.clone()
     }
+
+    #[wasm_bindgen(getter, js_name = "groupInfo")]
+    pub fn group_info(&self) -> Vec<u8> {
+        self.group_info.clone()
+    }
+}
+
+#[wasm_bindgen]
+pub struct WasmAddMembersResult {
+    commit: Option<Vec<u8>>,
+    welcome: Option<Vec<u8>>,
+    group_info: Option<Vec<u8>>,
+    rejected: String,
+}
+
+#[wasm_bindgen]
+impl WasmAddMembersResult {
+    #[wasm_bindgen(getter)]
+    pub fn commit(&self) -> Option<Vec<u8>> {
+        self.commit.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn welcome(&self) -> Option<Vec<u8>> {
+        self.welcome.clone()
+    }
+
+    #[wasm_bindgen(getter, js_name = "groupInfo")]
+    pub fn group_info(&self) -> Option<Vec<u8>> {
+        self.group_info.clone()
+    }
+
+    // JSON array of { index, code, error } for key packages left out of the commit
+    #[wasm_bindgen(getter)]
+    pub fn rejected(&self) -> String {
+        self.rejected.clone()
+    }
+}
+
+#[wasm_bindgen]
+pub struct WasmCommitResult {
+    commit: Vec<u8>,
+    welcome: Option<Vec<u8>>,
+    group_info: Vec<u8>,
+}
+
+#[wasm_bindgen]
+impl WasmCommitResult {
+    #[wasm_bindgen(getter)]
+    pub fn commit(&self) -> Vec<u8> {
+        self.commit.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn welcome(&self) -> Option<Vec<u8>> {
+        self.welcome.clone()
+    }
+
+    #[wasm_bindgen(getter, js_name = "groupInfo")]
+    pub fn group_info(&self) -> Vec<u8> {
+        self.group_info.clone()
+    }
+}
+
+#[wasm_bindgen]
+pub struct WasmExternalJoinResult {
+    group_id: Vec<u8>,
+    commit: Vec<u8>,
+    group_info: Vec<u8>,
+}
+
+#[wasm_bindgen]
+impl WasmExternalJoinResult {
+    #[wasm_bindgen(getter, js_name = "groupId")]
+    pub fn group_id(&self) -> Vec<u8> {
+        self.group_id.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn commit(&self) -> Vec<u8> {
+        self.commit.clone()
+    }
+
+    #[wasm_bindgen(getter, js_name = "groupInfo")]
+    pub fn group_info(&self) -> Vec<u8> {
+        self.group_info.clone()
+    }
+}
+
+#[wasm_bindgen]
+pub struct WasmProposalResult {
+    proposal: Vec<u8>,
+    proposal_ref: Vec<u8>,
+}
+
+#[wasm_bindgen]
+impl WasmProposalResult {
+    #[wasm_bindgen(getter)]
+    pub fn proposal(&self) -> Vec<u8> {
+        self.proposal.clone()
+    }
+
+    #[wasm_bindgen(getter, js_name = "proposalRef")]
+    pub fn proposal_ref(&self) -> Vec<u8> {
+        self.proposal_ref.clone()
+    }
 }
 
 #[wasm_bindgen]
@@ -305,6 +862,9 @@ pub struct WasmProcessedMessage {
     message_type: String,
     plaintext: Vec<u8>,
     sender_identity: Vec<u8>,
+    authenticated_data: Vec<u8>,
+    epoch: u64,
+    commit_details: Option<String>,
 }
 
 #[wasm_bindgen]
@@ -323,34 +883,279 @@ impl WasmProcessedMessage {
     pub fn sender_identity(&self) -> Vec<u8> {
         self.sender_identity.clone()
     }
+
+    #[wasm_bindgen(getter, js_name = "authenticatedData")]
+    pub fn authenticated_data(&self) -> Vec<u8> {
+        self.authenticated_data.clone()
+    }
+
+    // The epoch the message was sent in; lower than the group's for a late
+    // message decrypted with retained past-epoch secrets
+    #[wasm_bindgen(getter)]
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    // JSON summary of a merged commit; absent for other message types
+    #[wasm_bindgen(getter, js_name = "commitDetails")]
+    pub fn commit_details(&self) -> Option<String> {
+        self.commit_details.clone()
+    }
 }
 
-fn load_group(s: &session::Session, group_id: &[u8]) -> Result<MlsGroup, String> {
+fn load_group(s: &session::Session, group_id: &[u8]) -> Result<MlsGroup, MlsError> {
     let gid = GroupId::from_slice(group_id);
     MlsGroup::load(s.provider.storage(), &gid)
-        .map_err(|e| format!("load group: {:?}", e))?
-        .ok_or_else(|| "group not found in session".to_string())
+        .map_err(|e| MlsError::storage("load group", e))?
+        .ok_or_else(|| {
+            MlsError::new(ErrorCode::GroupNotFound, "group not found in session")
+                .with_group(group_id)
+        })
+}
+
+// Like load_group, for operations that send on the group's behalf. Once we
+// have asked to leave, only processing (the commit removing us) is allowed.
+fn load_active_group(s: &session::Session, group_id: &[u8]) -> Result<MlsGroup, MlsError> {
+    let group = load_group(s, group_id)?;
+    if has_left(s, group_id)? {
+        return Err(
+            MlsError::new(ErrorCode::LeftGroup, "group was left; waiting for removal")
+                .with_group(group_id),
+        );
+    }
+    Ok(group)
+}
+
+fn has_left(s: &session::Session, group_id: &[u8]) -> Result<bool, MlsError> {
+    let left: Option<bool> = s
+        .provider
+        .storage()
+        .read_meta(LEFT_GROUP_LABEL, group_id)
+        .map_err(|e| MlsError::storage("read left marker", e))?;
+    Ok(left.unwrap_or(false))
+}
+
+// Remove the group and our bookkeeping for it from the session's storage
+fn delete_group(s: &session::Session, group: &mut MlsGroup) -> Result<(), MlsError> {
+    let storage = s.provider.storage();
+    group
+        .delete(storage)
+        .map_err(|e| MlsError::storage("delete group", e))?;
+    for label in [LAST_SELF_UPDATE_LABEL, LEFT_GROUP_LABEL] {
+        storage
+            .delete_meta(label, group.group_id().as_slice())
+            .map_err(|e| MlsError::storage("delete group metadata", e))?;
+    }
+    Ok(())
+}
+
+fn record_self_update(s: &session::Session, group: &MlsGroup) -> Result<(), MlsError> {
+    s.provider
+        .storage()
+        .write_meta(
+            LAST_SELF_UPDATE_LABEL,
+            group.group_id().as_slice(),
+            &group.epoch().as_u64(),
+        )
+        .map_err(|e| MlsError::storage("record self update", e))
+}
+
+// Merge our own pending commit. A commit carrying an update path refreshed
+// our leaf's HPKE keys, so it counts as a self-update.
+fn merge_own_commit(s: &session::Session, group: &mut MlsGroup) -> Result<(), MlsError> {
+    let refreshed_leaf = group
+        .pending_commit()
+        .is_some_and(|commit| commit.update_path_leaf_node().is_some());
+
+    group
+        .merge_pending_commit(&s.provider)
+        .map_err(|e| MlsError::from_op("merge pending commit", e))?;
+
+    if refreshed_leaf {
+        record_self_update(s, group)?;
+    }
+    Ok(())
+}
+
+// Serialize the GroupInfo a commit produced, or export a fresh one if the
+// commit didn't carry it. Our groups use the ratchet tree extension, so
+// openmls always produces one; an export would describe the current epoch,
+// not the one a still-pending commit leads to.
+fn group_info_bytes(
+    s: &session::Session,
+    group: &MlsGroup,
+    group_info: Option<GroupInfo>,
+) -> Result<Vec<u8>, MlsError> {
+    let message = match group_info {
+        Some(group_info) => MlsMessageOut::from(group_info),
+        None => group
+            .export_group_info(s.provider.crypto(), &s.signer, true)
+            .map_err(|e| MlsError::from_op("export group info", e))?,
+    };
+    message
+        .tls_serialize_detached()
+        .map_err(|e| MlsError::internal("serialize group info", e))
 }
 
+fn commit_result(
+    s: &session::Session,
+    group: &MlsGroup,
+    commit_out: MlsMessageOut,
+    welcome_out: Option<MlsMessageOut>,
+    group_info: Option<GroupInfo>,
+) -> Result<WasmCommitResult, MlsError> {
+    let commit = commit_out
+        .tls_serialize_detached()
+        .map_err(|e| MlsError::internal("serialize commit", e))?;
+    let welcome = welcome_out
+        .map(|w| w.tls_serialize_detached())
+        .transpose()
+        .map_err(|e| MlsError::internal("serialize welcome", e))?;
+    let group_info = group_info_bytes(s, group, group_info)?;
+
+    Ok(WasmCommitResult {
+        commit,
+        welcome,
+        group_info,
+    })
+}
+
+fn validate_key_package(
+    s: &session::Session,
+    key_package_tls: &[u8],
+) -> Result<KeyPackage, MlsError> {
+    let kp_in = KeyPackageIn::tls_deserialize(&mut &key_package_tls[..])
+        .map_err(|e| MlsError::decode("deserialize key package", e))?;
+    kp_in
+        .validate(s.provider.crypto(), ProtocolVersion::Mls10)
+        .map_err(|e| MlsError::from_op("validate key package", e))
+}
+
+// The identity key behind a credential, whether bare or DID-bound
 fn extract_identity(credential: &Credential) -> Vec<u8> {
     match BasicCredential::try_from(credential.clone()) {
-        Ok(basic) => basic.identity().to_vec(),
+        Ok(basic) => match CairnIdentity::decode(basic.identity()) {
+            Ok(Some(cairn)) => cairn.identity_key,
+            _ => basic.identity().to_vec(),
+        },
         Err(_) => vec![],
     }
 }
 
+// Only a 32-byte identity is a legacy bare key; anything else must decode
+// as a Cairn identity, or the credential is malformed
+fn cairn_identity(credential: &Credential) -> Result<Option<CairnIdentity>, MlsError> {
+    let Ok(basic) = BasicCredential::try_from(credential.clone()) else {
+        return Ok(None);
+    };
+    CairnIdentity::decode(basic.identity()).map_err(|e| {
+        MlsError::new(
+            ErrorCode::InvalidCredential,
+            format!("invalid credential: {}", e.message()),
+        )
+    })
+}
+
+fn device_id(credential: &Credential) -> Result<Option<String>, MlsError> {
+    Ok(cairn_identity(credential)?
+        .and_then(|c| c.device)
+        .map(|d| d.id))
+}
+
+// What packages being added must not repeat: the signature keys and
+// (identity, device id) pairs already in the group or earlier in the batch.
+// openmls only catches the former; a second leaf for a device would leave
+// its user with two copies of that device.
+struct Additions {
+    signature_keys: Vec<Vec<u8>>,
+    devices: Vec<(Vec<u8>, String)>,
+}
+
+impl Additions {
+    fn new(group: &MlsGroup) -> Result<Self, MlsError> {
+        let mut devices = Vec::new();
+        for member in group.members() {
+            if let Some(device) = device_id(&member.credential)? {
+                devices.push((extract_identity(&member.credential), device));
+            }
+        }
+        Ok(Self {
+            signature_keys: group.members().map(|m| m.signature_key).collect(),
+            devices,
+        })
+    }
+
+    // Check a package against the group and the batch so far, then count it
+    // as part of the batch
+    fn admit(
+        &mut self,
+        s: &session::Session,
+        group: &MlsGroup,
+        kp: &KeyPackage,
+    ) -> Result<(), MlsError> {
+        if kp.ciphersuite() != group.ciphersuite() {
+            return Err(MlsError::new(
+                ErrorCode::UnsupportedCiphersuite,
+                format!(
+                    "ciphersuite {:?} does not match the group's {:?}",
+                    kp.ciphersuite(),
+                    group.ciphersuite()
+                ),
+            ));
+        }
+        let leaf_node = kp.leaf_node();
+        let signature_key = leaf_node.signature_key().as_slice().to_vec();
+        if self.signature_keys.contains(&signature_key) {
+            return Err(MlsError::new(
+                ErrorCode::InvalidKeyPackage,
+                "signature key is already a member or earlier in the batch",
+            ));
+        }
+        let device = device_id(leaf_node.credential())?
+            .map(|device| (extract_identity(leaf_node.credential()), device));
+        if let Some((_, id)) = device.as_ref().filter(|d| self.devices.contains(d)) {
+            return Err(MlsError::new(
+                ErrorCode::InvalidKeyPackage,
+                format!("device {} is already a member or earlier in the batch", id),
+            ));
+        }
+        check_leaf_node(s, leaf_node)?;
+        self.signature_keys.push(signature_key);
+        self.devices.extend(device);
+        Ok(())
+    }
+}
+
 #[wasm_bindgen]
-pub fn create_mls_group(session_id: u32, group_id: &[u8]) -> Result<(), JsError> {
+pub fn create_mls_group(
+    session_id: u32,
+    group_id: &[u8],
+    ciphersuite: Option<u16>,
+    options: JsValue,
+) -> Result<(), MlsError> {
+    let options: config::GroupOptions = config::parse(options, "group options")?;
     session::with_session(session_id, |s| {
-        let _group = MlsGroup::builder()
+        let ciphersuite = match ciphersuite {
+            Some(_) => resolve_ciphersuite(ciphersuite)?,
+            None => s.ciphersuite,
+        };
+        if ciphersuite.signature_algorithm() != s.signer.signature_scheme() {
+            return Err(MlsError::invalid_input(format!(
+                "ciphersuite {:?} does not match the session's signing key",
+                ciphersuite
+            )));
+        }
+
+        let builder = MlsGroup::builder()
             .with_group_id(GroupId::from_slice(group_id))
-            .ciphersuite(CIPHERSUITE)
-            .use_ratchet_tree_extension(true)
+            .ciphersuite(ciphersuite)
+            .use_ratchet_tree_extension(true);
+        let group = options
+            .apply(builder)?
             .build(&s.provider, &s.signer, s.credential_with_key.clone())
-            .map_err(|e| format!("create group: {:?}", e))?;
-        Ok(())
+            .map_err(|e| MlsError::internal("create group", e))?;
+        record_self_update(s, &group)
     })
-    .map_err(|e| JsError::new(&e))
 }
 
 #[wasm_bindgen]
@@ -358,94 +1163,391 @@ pub fn add_member(
     session_id: u32,
     group_id: &[u8],
     key_package_tls: &[u8],
-) -> Result<WasmAddMemberResult, JsError> {
+) -> Result<WasmAddMemberResult, MlsError> {
     session::with_session(session_id, |s| {
-        let mut group = load_group(s, group_id)?;
+        let mut group = load_active_group(s, group_id)?;
 
         // Deserialize and validate the KeyPackage
-        let kp_in = KeyPackageIn::tls_deserialize(&mut &key_package_tls[..])
-            .map_err(|e| format!("deserialize key package: {:?}", e))?;
-        let kp = kp_in
-            .validate(s.provider.crypto(), ProtocolVersion::Mls10)
-            .map_err(|e| format!("validate key package: {:?}", e))?;
-
-        // Add member (returns commit + welcome)
-        let (commit_out, welcome_out, _group_info) = group
-            .add_members(&s.provider, &s.signer, &[kp])
-            .map_err(|e| format!("add member: {:?}", e))?;
+        let kp = validate_key_package(s, key_package_tls)?;
+        Additions::new(&group)?.admit(s, &group, &kp)?;
 
-        // Merge the pending commit on the adder's side
-        group
-            .merge_pending_commit(&s.provider)
-            .map_err(|e| format!("merge pending commit: {:?}", e))?;
+        // Add member (returns commit + welcome). Like every commit we make, it
+        // stays pending until the caller confirms the server accepted it.
+        let (commit_out, welcome_out, group_info) = group
+            .add_members(&s.provider, &s.signer, &[kp])
+            .map_err(|e| MlsError::from_op("add member", e))?;
 
         // Serialize outputs
         let commit_bytes = commit_out
             .tls_serialize_detached()
-            .map_err(|e| format!("serialize commit: {:?}", e))?;
+            .map_err(|e| MlsError::internal("serialize commit", e))?;
         let welcome_bytes = welcome_out
             .tls_serialize_detached()
-            .map_err(|e| format!("serialize welcome: {:?}", e))?;
+            .map_err(|e| MlsError::internal("serialize welcome", e))?;
+        let group_info_bytes = group_info_bytes(s, &group, group_info)?;
 
         Ok(WasmAddMemberResult {
             commit: commit_bytes,
             welcome: welcome_bytes,
+            group_info: group_info_bytes,
         })
     })
-    .map_err(|e| JsError::new(&e))
 }
 
 #[wasm_bindgen]
-pub fn remove_member(
+pub fn add_members(
     session_id: u32,
     group_id: &[u8],
-    leaf_index: u32,
-) -> Result<Vec<u8>, JsError> {
+    key_packages: Vec<js_sys::Uint8Array>,
+) -> Result<WasmAddMembersResult, MlsError> {
     session::with_session(session_id, |s| {
-        let mut group = load_group(s, group_id)?;
-
-        let (commit_out, _welcome, _group_info) = group
-            .remove_members(&s.provider, &s.signer, &[LeafNodeIndex::new(leaf_index)])
-            .map_err(|e| format!("remove member: {:?}", e))?;
+        let mut group = load_active_group(s, group_id)?;
+
+        // Validate each package on its own so one bad invite doesn't sink the batch.
+        // Packages repeating a signature key or device already in the group (or
+        // earlier in the batch) would make the whole commit invalid, so they are
+        // rejected here too.
+        let mut additions = Additions::new(&group)?;
+        let mut valid = Vec::new();
+        let mut rejected = Vec::new();
+        for (index, bytes) in key_packages.iter().enumerate() {
+            let checked = validate_key_package(s, &bytes.to_vec())
+                .and_then(|kp| additions.admit(s, &group, &kp).map(|()| kp));
+            match checked {
+                Ok(kp) => valid.push(kp),
+                Err(error) => rejected.push(serde_json::json!({
+                    "index": index,
+                    "code": error.code().as_str(),
+                    "error": error.message(),
+                })),
+            }
+        }
+        let rejected = serde_json::to_string(&rejected)
+            .map_err(|e| MlsError::internal("serialize rejected", e))?;
+
+        if valid.is_empty() {
+            return Ok(WasmAddMembersResult {
+                commit: None,
+                welcome: None,
+                group_info: None,
+                rejected,
+            });
+        }
 
-        group
-            .merge_pending_commit(&s.provider)
-            .map_err(|e| format!("merge pending commit: {:?}", e))?;
+        let (commit_out, welcome_out, group_info) = group
+            .add_members(&s.provider, &s.signer, &valid)
+            .map_err(|e| MlsError::from_op("add members", e))?;
 
-        commit_out
-            .tls_serialize_detached()
-            .map_err(|e| format!("serialize commit: {:?}", e))
+        let result = commit_result(s, &group, commit_out, Some(welcome_out), group_info)?;
+        Ok(WasmAddMembersResult {
+            commit: Some(result.commit),
+            welcome: result.welcome,
+            group_info: Some(result.group_info),
+            rejected,
+        })
     })
-    .map_err(|e| JsError::new(&e))
 }
 
 #[wasm_bindgen]
-pub fn process_welcome(session_id: u32, welcome_tls: &[u8]) -> Result<Vec<u8>, JsError> {
+pub fn remove_member(
+    session_id: u32,
+    group_id: &[u8],
+    leaf_index: u32,
+) -> Result<WasmCommitResult, MlsError> {
     session::with_session(session_id, |s| {
-        let mls_msg_in = MlsMessageIn::tls_deserialize(&mut &welcome_tls[..])
-            .map_err(|e| format!("deserialize welcome: {:?}", e))?;
-
-        let welcome = match mls_msg_in.extract() {
-            MlsMessageBodyIn::Welcome(w) => w,
-            _ => return Err("expected Welcome message".to_string()),
-        };
+        let mut group = load_active_group(s, group_id)?;
 
-        let join_config = MlsGroupJoinConfig::builder()
-            .use_ratchet_tree_extension(true)
-            .build();
+        let (commit_out, welcome_out, group_info) = group
+            .remove_members(&s.provider, &s.signer, &[LeafNodeIndex::new(leaf_index)])
+            .map_err(|e| MlsError::from_op("remove member", e))?;
 
-        let staged = StagedWelcome::new_from_welcome(&s.provider, &join_config, welcome, None)
-            .map_err(|e| format!("stage welcome: {:?}", e))?;
+        commit_result(s, &group, commit_out, welcome_out, group_info)
+    })
+}
+
+/// Add all of one user's devices in a single commit, so the user never shows
+/// up in the group with only some of them. Unlike `add_members` this is all
+/// or nothing: every key package must be valid, carry the same identity and
+/// name a device not already in the group, or nothing is committed.
+#[wasm_bindgen]
+pub fn add_user_devices(
+    session_id: u32,
+    group_id: &[u8],
+    key_packages: Vec<js_sys::Uint8Array>,
+) -> Result<WasmAddMemberResult, MlsError> {
+    session::with_session(session_id, |s| {
+        let mut group = load_active_group(s, group_id)?;
+        let Some(first) = key_packages.first() else {
+            return Err(MlsError::invalid_input("no key packages to add"));
+        };
+        let identity = extract_identity(
+            validate_key_package(s, &first.to_vec())?
+                .leaf_node()
+                .credential(),
+        );
+
+        let mut additions = Additions::new(&group)?;
+        let mut packages = Vec::new();
+        for (index, bytes) in key_packages.iter().enumerate() {
+            let in_package = |e: MlsError| {
+                MlsError::new(e.code(), format!("key package {}: {}", index, e.message()))
+            };
+            let kp = validate_key_package(s, &bytes.to_vec()).map_err(in_package)?;
+            if extract_identity(kp.leaf_node().credential()) != identity {
+                return Err(in_package(MlsError::invalid_input(
+                    "belongs to a different identity than the first package",
+                )));
+            }
+            additions.admit(s, &group, &kp).map_err(in_package)?;
+            packages.push(kp);
+        }
+
+        let (commit_out, welcome_out, group_info) = group
+            .add_members(&s.provider, &s.signer, &packages)
+            .map_err(|e| MlsError::from_op("add user devices", e))?;
+
+        let result = commit_result(s, &group, commit_out, Some(welcome_out), group_info)?;
+        Ok(WasmAddMemberResult {
+            commit: result.commit,
+            welcome: result.welcome.unwrap_or_default(),
+            group_info: result.group_info,
+        })
+    })
+}
+
+/// Remove every leaf of each identity in one commit. Leaf indices are looked
+/// up against the current tree, so callers never hold stale ones. Our own
+/// leaf is skipped (use `leave_group`); fails with `member_not_found` if an
+/// identity has no other leaf in the group.
+#[wasm_bindgen]
+pub fn remove_members_by_identity(
+    session_id: u32,
+    group_id: &[u8],
+    identities: Vec<js_sys::Uint8Array>,
+) -> Result<WasmCommitResult, MlsError> {
+    session::with_session(session_id, |s| {
+        let mut group = load_active_group(s, group_id)?;
+        if identities.is_empty() {
+            return Err(MlsError::invalid_input("no identities to remove"));
+        }
+
+        let own_index = group.own_leaf_index();
+        let mut leaves = Vec::new();
+        for (position, identity) in identities.iter().enumerate() {
+            let identity = identity.to_vec();
+            let matches: Vec<LeafNodeIndex> = group
+                .members()
+                .filter(|m| m.index != own_index && extract_identity(&m.credential) == identity)
+                .map(|m| m.index)
+                .collect();
+            if matches.is_empty() {
+                return Err(MlsError::new(
+                    ErrorCode::MemberNotFound,
+                    format!("identity {} has no removable leaf", position),
+                )
+                .with_group(group_id));
+            }
+            for index in matches {
+                if !leaves.contains(&index) {
+                    leaves.push(index);
+                }
+            }
+        }
+
+        let (commit_out, welcome_out, group_info) = group
+            .remove_members(&s.provider, &s.signer, &leaves)
+            .map_err(|e| MlsError::from_op("remove members", e))?;
+
+        commit_result(s, &group, commit_out, welcome_out, group_info)
+    })
+}
+
+#[wasm_bindgen]
+pub fn self_update(session_id: u32, group_id: &[u8]) -> Result<WasmCommitResult, MlsError> {
+    session::with_session(session_id, |s| {
+        let mut group = load_active_group(s, group_id)?;
+
+        let bundle = group
+            .self_update(&s.provider, &s.signer, LeafNodeParameters::default())
+            .map_err(|e| MlsError::from_op("self update", e))?;
+
+        let commit_out = bundle.commit().clone();
+        let welcome_out = bundle.to_welcome_msg();
+        commit_result(s, &group, commit_out, welcome_out, bundle.into_group_info())
+    })
+}
+
+#[wasm_bindgen]
+pub fn process_welcome(
+    session_id: u32,
+    welcome_tls: &[u8],
+    options: JsValue,
+) -> Result<Vec<u8>, MlsError> {
+    let options: config::GroupOptions = config::parse(options, "group options")?;
+    let join_config = options.join_config()?;
+    session::with_session(session_id, |s| {
+        join_group(s, welcome_tls, &join_config, None)
+    })
+}
+
+/// Join a group branched from `parent_group_id` by `branch_group`. The
+/// Welcome's key schedule includes the parent's resumption secret, and which
+/// parent it names is only readable once that secret is supplied, so the
+/// caller says which local group is the parent. Fails with `psk_not_found`
+/// if we no longer hold the parent epoch the branch was made from.
+#[wasm_bindgen]
+pub fn process_branch_welcome(
+    session_id: u32,
+    welcome_tls: &[u8],
+    parent_group_id: &[u8],
+    options: JsValue,
+) -> Result<Vec<u8>, MlsError> {
+    let options: config::GroupOptions = config::parse(options, "group options")?;
+    let join_config = options.join_config()?;
+    session::with_session(session_id, |s| {
+        join_group(s, welcome_tls, &join_config, Some(parent_group_id))
+    })
+}
+
+fn join_group(
+    s: &session::Session,
+    welcome_tls: &[u8],
+    join_config: &MlsGroupJoinConfig,
+    parent_group_id: Option<&[u8]>,
+) -> Result<Vec<u8>, MlsError> {
+    let mls_msg_in = MlsMessageIn::tls_deserialize(&mut &welcome_tls[..])
+        .map_err(|e| MlsError::decode("deserialize welcome", e))?;
+
+    let welcome = match mls_msg_in.extract() {
+        MlsMessageBodyIn::Welcome(w) => w,
+        _ => return Err(MlsError::invalid_input("expected Welcome message")),
+    };
+
+    // Removed again when this returns, whatever the outcome
+    let mut branch_psks = psk::TemporaryPsks::new(&s.provider);
+    if let Some(parent_group_id) = parent_group_id {
+        register_branch_psks(&load_group(s, parent_group_id)?, &mut branch_psks)?;
+    }
+    let used = welcome_key_package(s, &welcome)?;
+    let processed = ProcessedWelcome::new_from_welcome(&s.provider, join_config, welcome)
+        .map_err(|e| MlsError::from_op("stage welcome", e))?;
+    if let Some(hash_ref) = &used {
+        record_used_key_package(s, hash_ref)?;
+    }
+
+    if parent_group_id.is_some() {
+        let from_parent = processed.psks().iter().any(|psk_id| match psk_id.psk() {
+            Psk::External(external) => branch_psks.contains(external.psk_id()),
+            _ => false,
+        });
+        if !from_parent {
+            return Err(MlsError::invalid_input(
+                "Welcome is not for a branch of the given parent group",
+            ));
+        }
+    }
+
+    let staged = processed
+        .into_staged_welcome(&s.provider, None)
+        .map_err(|e| MlsError::from_op("stage welcome", e))?;
+    let group_id = staged.group_context().group_id().as_slice().to_vec();
+
+    // Vet everyone already in the group before the group is stored. A
+    // rejected Welcome leaves the session as it was, key package included.
+    for member in staged.members() {
+        check_credential(
+            s,
+            &member.credential,
+            &SignaturePublicKey::from(member.signature_key),
+        )
+        .map_err(|e| e.with_group(&group_id))?;
+    }
 
-        let group_id = staged.group_context().group_id().as_slice().to_vec();
+    let group = staged
+        .into_group(&s.provider)
+        .map_err(|e| MlsError::internal("finalize welcome", e))?;
+    record_self_update(s, &group)?;
 
-        let _group = staged
-            .into_group(&s.provider)
-            .map_err(|e| format!("finalize welcome: {:?}", e))?;
+    Ok(group_id)
+}
+
+#[wasm_bindgen]
+pub fn join_by_external_commit(
+    session_id: u32,
+    group_info_tls: &[u8],
+    ratchet_tree: Option<Vec<u8>>,
+    options: JsValue,
+) -> Result<WasmExternalJoinResult, MlsError> {
+    let options: config::GroupOptions = config::parse(options, "group options")?;
+    let join_config = options.join_config()?;
+    session::with_session(session_id, |s| {
+        let mls_msg_in = MlsMessageIn::tls_deserialize(&mut &group_info_tls[..])
+            .map_err(|e| MlsError::decode("deserialize group info", e))?;
+
+        let group_info = match mls_msg_in.extract() {
+            MlsMessageBodyIn::GroupInfo(gi) => gi,
+            _ => return Err(MlsError::invalid_input("expected GroupInfo message")),
+        };
+        let group_id = group_info.group_id().clone();
+
+        let mut builder = MlsGroup::external_commit_builder().with_config(join_config);
+        if let Some(tree) = ratchet_tree {
+            let tree = RatchetTreeIn::tls_deserialize(&mut &tree[..])
+                .map_err(|e| MlsError::decode("deserialize ratchet tree", e))?;
+            builder = builder.with_ratchet_tree(tree);
+        }
+
+        let commit_builder = builder
+            .build_group(&s.provider, group_info, s.credential_with_key.clone())
+            .map_err(|e| MlsError::from_op("external join", e))?
+            .load_psks(s.provider.storage())
+            .map_err(|e| MlsError::internal("load psks", e))?
+            .build(s.provider.rand(), s.provider.crypto(), &s.signer, |_| true)
+            .map_err(|e| MlsError::internal("build external commit", e))?;
+
+        // Rejoining after a desync: our stale copy of the group is useless and
+        // would collide with the new one in storage, so drop it. Only now that
+        // the commit is built, so a join that fails keeps the copy we have.
+        // The external commit removes our old leaf from the tree.
+        if let Some(mut stale) = MlsGroup::load(s.provider.storage(), &group_id)
+            .map_err(|e| MlsError::storage("load group", e))?
+        {
+            delete_group(s, &mut stale)?;
+        }
+
+        // Unlike our other commits this one is merged right away: openmls
+        // has no pending state for a group we are only joining
+        let (mut group, bundle) = commit_builder
+            .finalize(&s.provider)
+            .map_err(|e| MlsError::internal("finalize external commit", e))?;
+
+        // The GroupInfo and tree come from the server, so vet every member
+        // as for a Welcome. A refused join leaves no group behind.
+        for member in group.members().collect::<Vec<_>>() {
+            let checked = check_credential(
+                s,
+                &member.credential,
+                &SignaturePublicKey::from(member.signature_key),
+            );
+            if let Err(e) = checked {
+                delete_group(s, &mut group)?;
+                return Err(e.with_group(group_id.as_slice()));
+            }
+        }
+        record_self_update(s, &group)?;
 
-        Ok(group_id)
+        let commit = bundle
+            .commit()
+            .tls_serialize_detached()
+            .map_err(|e| MlsError::internal("serialize commit", e))?;
+        let group_info = group_info_bytes(s, &group, bundle.into_group_info())?;
+
+        Ok(WasmExternalJoinResult {
+            group_id: group_id.as_slice().to_vec(),
+            commit,
+            group_info,
+        })
     })
-    .map_err(|e| JsError::new(&e))
 }
 
 #[wasm_bindgen]
@@ -453,19 +1555,144 @@ pub fn encrypt_message(
     session_id: u32,
     group_id: &[u8],
     plaintext: &[u8],
-) -> Result<Vec<u8>, JsError> {
+    aad: Option<Vec<u8>>,
+) -> Result<Vec<u8>, MlsError> {
     session::with_session(session_id, |s| {
-        let mut group = load_group(s, group_id)?;
+        let mut group = load_active_group(s, group_id)?;
+
+        // Authenticated but unencrypted; openmls clears it after this message
+        if let Some(aad) = aad {
+            group.set_aad(aad);
+        }
 
         let msg_out = group
             .create_message(&s.provider, &s.signer, plaintext)
-            .map_err(|e| format!("encrypt: {:?}", e))?;
+            .map_err(|e| MlsError::from_op("encrypt", e))?;
 
         msg_out
             .tls_serialize_detached()
-            .map_err(|e| format!("serialize: {:?}", e))
+            .map_err(|e| MlsError::internal("serialize", e))
     })
-    .map_err(|e| JsError::new(&e))
+}
+
+// What a commit changed, captured from the staged commit before merging
+// (removed members' credentials are gone afterwards) and completed after
+// (added members only get leaf indices once the commit is applied).
+struct CommitSummary {
+    epoch: u64,
+    added_keys: Vec<Vec<u8>>,
+    removed: Vec<serde_json::Value>,
+    updated: Vec<LeafNodeIndex>,
+    extensions_changed: Vec<u16>,
+    self_removed: bool,
+    pending_commit_discarded: bool,
+}
+
+impl CommitSummary {
+    fn stage(group: &MlsGroup, staged: &StagedCommit, sender: &Sender) -> Self {
+        let removed = staged
+            .remove_proposals()
+            .map(|p| {
+                let index = p.remove_proposal().removed();
+                serde_json::json!({
+                    "index": index.u32(),
+                    "identity": group.member(index).map(extract_identity).unwrap_or_default(),
+                })
+            })
+            .collect();
+
+        let mut added_keys: Vec<Vec<u8>> = staged
+            .add_proposals()
+            .map(|p| {
+                p.add_proposal()
+                    .key_package()
+                    .leaf_node()
+                    .signature_key()
+                    .as_slice()
+                    .to_vec()
+            })
+            .collect();
+
+        let mut updated: Vec<LeafNodeIndex> = staged
+            .update_proposals()
+            .filter_map(|p| match p.sender() {
+                Sender::Member(index) => Some(*index),
+                _ => None,
+            })
+            .collect();
+        if let Some(leaf) = staged.update_path_leaf_node() {
+            match sender {
+                // The committer refreshed its own keys via the update path
+                Sender::Member(index) => updated.push(*index),
+                // An external joiner's path leaf is its new leaf
+                Sender::NewMemberCommit => {
+                    added_keys.push(leaf.signature_key().as_slice().to_vec())
+                }
+                _ => {}
+            }
+        }
+        updated.sort();
+        updated.dedup();
+
+        let old = group.extensions();
+        let new = staged.group_context().extensions();
+        let mut extensions_changed: Vec<u16> = old
+            .iter()
+            .chain(new.iter())
+            .map(|e| e.extension_type())
+            .filter(|t| {
+                old.iter().find(|e| e.extension_type() == *t)
+                    != new.iter().find(|e| e.extension_type() == *t)
+            })
+            .map(u16::from)
+            .collect();
+        extensions_changed.sort();
+        extensions_changed.dedup();
+
+        CommitSummary {
+            epoch: staged.group_context().epoch().as_u64(),
+            added_keys,
+            removed,
+            updated,
+            extensions_changed,
+            self_removed: staged.self_removed(),
+            pending_commit_discarded: false,
+        }
+    }
+
+    fn finish(self, group: &MlsGroup) -> Result<String, MlsError> {
+        let added: Vec<serde_json::Value> = group
+            .members()
+            .filter(|m| self.added_keys.contains(&m.signature_key))
+            .map(|m| {
+                serde_json::json!({
+                    "index": m.index.u32(),
+                    "identity": extract_identity(&m.credential),
+                })
+            })
+            .collect();
+        let updated: Vec<serde_json::Value> = self
+            .updated
+            .iter()
+            .map(|index| {
+                serde_json::json!({
+                    "index": index.u32(),
+                    "identity": group.member(*index).map(extract_identity).unwrap_or_default(),
+                })
+            })
+            .collect();
+
+        serde_json::to_string(&serde_json::json!({
+            "epoch": self.epoch,
+            "added": added,
+            "removed": self.removed,
+            "updated": updated,
+            "extensions_changed": self.extensions_changed,
+            "self_removed": self.self_removed,
+            "pending_commit_discarded": self.pending_commit_discarded,
+        }))
+        .map_err(|e| MlsError::internal("serialize commit details", e))
+    }
 }
 
 #[wasm_bindgen]
@@ -473,85 +1700,613 @@ pub fn process_group_message(
     session_id: u32,
     group_id: &[u8],
     message_tls: &[u8],
-) -> Result<WasmProcessedMessage, JsError> {
+) -> Result<WasmProcessedMessage, MlsError> {
     session::with_session(session_id, |s| {
         let mut group = load_group(s, group_id)?;
 
         let mls_msg_in = MlsMessageIn::tls_deserialize(&mut &message_tls[..])
-            .map_err(|e| format!("deserialize message: {:?}", e))?;
+            .map_err(|e| MlsError::decode("deserialize message", e))?;
 
         // Extract the protocol message from the MLS envelope
-        let body = mls_msg_in.extract();
-        let processed = match body {
-            MlsMessageBodyIn::PublicMessage(m) => group
-                .process_message(&s.provider, m)
-                .map_err(|e| format!("process message: {:?}", e))?,
-            MlsMessageBodyIn::PrivateMessage(m) => group
-                .process_message(&s.provider, m)
-                .map_err(|e| format!("process message: {:?}", e))?,
-            _ => return Err("expected PublicMessage or PrivateMessage".to_string()),
+        let message: ProtocolMessage = match mls_msg_in.extract() {
+            MlsMessageBodyIn::PublicMessage(m) => m.into(),
+            MlsMessageBodyIn::PrivateMessage(m) => m.into(),
+            _ => {
+                return Err(MlsError::invalid_input(
+                    "expected PublicMessage or PrivateMessage",
+                ))
+            }
         };
+        // Errors carry the message's epoch so callers can tell a message from
+        // the future (buffer it) from one that is too old (drop it)
+        let message_epoch = message.epoch().as_u64();
+        let processed = group.process_message(&s.provider, message).map_err(|e| {
+            MlsError::from_op("process message", e)
+                .with_group(group_id)
+                .with_epoch(message_epoch)
+        })?;
 
         let sender_identity = extract_identity(processed.credential());
+        let sender = processed.sender().clone();
+        let authenticated_data = processed.aad().to_vec();
 
         match processed.into_content() {
             ProcessedMessageContent::ApplicationMessage(app_msg) => Ok(WasmProcessedMessage {
                 message_type: "application".to_string(),
                 plaintext: app_msg.into_bytes(),
                 sender_identity,
+                authenticated_data,
+                epoch: message_epoch,
+                commit_details: None,
             }),
             ProcessedMessageContent::StagedCommitMessage(staged_commit) => {
+                // A refused commit is never merged; the group stays at its current epoch
+                check_staged_commit(s, &staged_commit)
+                    .map_err(|e| e.with_group(group_id).with_epoch(message_epoch))?;
+                let mut summary = CommitSummary::stage(&group, &staged_commit, &sender);
+                // Another member's commit for the epoch we were also committing to
+                // won the race at the server. Merging it drops ours, which the
+                // caller has to redo in the new epoch.
+                summary.pending_commit_discarded = group.pending_commit().is_some();
+                // Committing our propose_self_update refreshes our leaf too
+                let own_leaf = Sender::Member(group.own_leaf_index());
+                let refreshed_leaf = staged_commit
+                    .update_proposals()
+                    .any(|update| *update.sender() == own_leaf);
                 group
                     .merge_staged_commit(&s.provider, *staged_commit)
-                    .map_err(|e| format!("merge commit: {:?}", e))?;
+                    .map_err(|e| MlsError::internal("merge commit", e))?;
+                if refreshed_leaf {
+                    record_self_update(s, &group)?;
+                }
+                let self_removed = summary.self_removed;
+                let commit_details = summary.finish(&group)?;
+                // The removal we asked for in leave_group: nothing more can
+                // happen in this group, so its state goes
+                if self_removed && has_left(s, group_id)? {
+                    delete_group(s, &mut group)?;
+                }
                 Ok(WasmProcessedMessage {
                     message_type: "commit".to_string(),
                     plaintext: vec![],
                     sender_identity,
+                    authenticated_data,
+                    epoch: message_epoch,
+                    commit_details: Some(commit_details),
                 })
             }
-            ProcessedMessageContent::ProposalMessage(_proposal) => Ok(WasmProcessedMessage {
-                message_type: "proposal".to_string(),
-                plaintext: vec![],
-                sender_identity,
-            }),
-            ProcessedMessageContent::ExternalJoinProposalMessage(_) => Ok(WasmProcessedMessage {
-                message_type: "external_proposal".to_string(),
-                plaintext: vec![],
-                sender_identity,
+            ProcessedMessageContent::ProposalMessage(proposal) => {
+                check_proposal(s, proposal.proposal())
+                    .map_err(|e| e.with_group(group_id).with_epoch(message_epoch))?;
+                // Queue it so a later commit (ours or another member's) can include it
+                group
+                    .store_pending_proposal(s.provider.storage(), *proposal)
+                    .map_err(|e| MlsError::storage("store proposal", e))?;
+                Ok(WasmProcessedMessage {
+                    message_type: "proposal".to_string(),
+                    plaintext: vec![],
+                    sender_identity,
+                    authenticated_data,
+                    epoch: message_epoch,
+                    commit_details: None,
+                })
+            }
+            ProcessedMessageContent::ExternalJoinProposalMessage(proposal) => {
+                check_proposal(s, proposal.proposal())
+                    .map_err(|e| e.with_group(group_id).with_epoch(message_epoch))?;
+                group
+                    .store_pending_proposal(s.provider.storage(), *proposal)
+                    .map_err(|e| MlsError::storage("store proposal", e))?;
+                Ok(WasmProcessedMessage {
+                    message_type: "external_proposal".to_string(),
+                    plaintext: vec![],
+                    sender_identity,
+                    authenticated_data,
+                    epoch: message_epoch,
+                    commit_details: None,
+                })
+            }
+        }
+    })
+}
+
+// ==================== Proposals ====================
+
+fn proposal_result(
+    message: MlsMessageOut,
+    proposal_ref: ProposalRef,
+) -> Result<WasmProposalResult, MlsError> {
+    Ok(WasmProposalResult {
+        proposal: message
+            .tls_serialize_detached()
+            .map_err(|e| MlsError::internal("serialize proposal", e))?,
+        proposal_ref: proposal_ref.as_slice().to_vec(),
+    })
+}
+
+#[wasm_bindgen]
+pub fn propose_add(
+    session_id: u32,
+    group_id: &[u8],
+    key_package_tls: &[u8],
+) -> Result<WasmProposalResult, MlsError> {
+    session::with_session(session_id, |s| {
+        let mut group = load_active_group(s, group_id)?;
+        let kp = validate_key_package(s, key_package_tls)?;
+        check_leaf_node(s, kp.leaf_node())?;
+
+        let (message, proposal_ref) = group
+            .propose_add_member(&s.provider, &s.signer, &kp)
+            .map_err(|e| MlsError::from_op("propose add", e))?;
+        proposal_result(message, proposal_ref)
+    })
+}
+
+#[wasm_bindgen]
+pub fn propose_remove(
+    session_id: u32,
+    group_id: &[u8],
+    leaf_index: u32,
+) -> Result<WasmProposalResult, MlsError> {
+    session::with_session(session_id, |s| {
+        let mut group = load_active_group(s, group_id)?;
+
+        let (message, proposal_ref) = group
+            .propose_remove_member(&s.provider, &s.signer, LeafNodeIndex::new(leaf_index))
+            .map_err(|e| MlsError::from_op("propose remove", e))?;
+        proposal_result(message, proposal_ref)
+    })
+}
+
+#[wasm_bindgen]
+pub fn propose_self_update(
+    session_id: u32,
+    group_id: &[u8],
+) -> Result<WasmProposalResult, MlsError> {
+    session::with_session(session_id, |s| {
+        let mut group = load_active_group(s, group_id)?;
+
+        let (message, proposal_ref) = group
+            .propose_self_update(&s.provider, &s.signer, LeafNodeParameters::default())
+            .map_err(|e| MlsError::from_op("propose self update", e))?;
+        proposal_result(message, proposal_ref)
+    })
+}
+
+/// Ask to leave the group. Returns a proposal removing our own leaf, which
+/// another member has to commit. From then on the group refuses to send, and
+/// its state is deleted once the commit removing us is processed. Calling it
+/// again produces a fresh proposal, e.g. to resend in a later epoch.
+#[wasm_bindgen]
+pub fn leave_group(session_id: u32, group_id: &[u8]) -> Result<Vec<u8>, MlsError> {
+    session::with_session(session_id, |s| {
+        let mut group = load_group(s, group_id)?;
+
+        let message = group
+            .leave_group(&s.provider, &s.signer)
+            .map_err(|e| MlsError::from_op("leave group", e).with_group(group_id))?;
+        s.provider
+            .storage()
+            .write_meta(LEFT_GROUP_LABEL, group_id, &true)
+            .map_err(|e| MlsError::storage("record left group", e))?;
+
+        message
+            .tls_serialize_detached()
+            .map_err(|e| MlsError::internal("serialize proposal", e))
+    })
+}
+
+#[wasm_bindgen]
+pub fn commit_pending_proposals(
+    session_id: u32,
+    group_id: &[u8],
+) -> Result<WasmCommitResult, MlsError> {
+    session::with_session(session_id, |s| {
+        let mut group = load_active_group(s, group_id)?;
+
+        let (commit_out, welcome_out, group_info) = group
+            .commit_to_pending_proposals(&s.provider, &s.signer)
+            .map_err(|e| MlsError::from_op("commit pending proposals", e))?;
+
+        commit_result(s, &group, commit_out, welcome_out, group_info)
+    })
+}
+
+#[wasm_bindgen]
+pub fn get_pending_proposals(session_id: u32, group_id: &[u8]) -> Result<String, MlsError> {
+    session::with_session(session_id, |s| {
+        let group = load_group(s, group_id)?;
+        let proposals: Vec<serde_json::Value> = group
+            .pending_proposals()
+            .map(|p| {
+                let sender = match p.sender() {
+                    Sender::Member(index) => Some(index.u32()),
+                    _ => None,
+                };
+                serde_json::json!({
+                    "proposal_ref": p.proposal_reference_ref().as_slice(),
+                    "proposal_type": format!("{:?}", p.proposal().proposal_type()),
+                    "sender_index": sender,
+                })
+            })
+            .collect();
+        serde_json::to_string(&proposals).map_err(|e| MlsError::internal("serialize proposals", e))
+    })
+}
+
+// ==================== Pre-Shared Keys ====================
+
+/// Store an external PSK in the session under `psk_id`. Every member has to
+/// register the same secret before processing a commit or Welcome that uses
+/// it; registering an id again replaces its secret.
+#[wasm_bindgen]
+pub fn register_external_psk(
+    session_id: u32,
+    psk_id: &[u8],
+    secret: &[u8],
+) -> Result<(), MlsError> {
+    session::with_session(session_id, |s| psk::store(&s.provider, psk_id, secret))
+}
+
+#[wasm_bindgen]
+pub fn remove_external_psk(session_id: u32, psk_id: &[u8]) -> Result<(), MlsError> {
+    session::with_session(session_id, |s| psk::delete(&s.provider, psk_id))
+}
+
+/// Propose mixing the registered external PSK `psk_id` into the next epoch.
+/// `commit_pending_proposals` commits it along with any other queued
+/// proposals; fails with `psk_not_found` there if the secret is missing.
+#[wasm_bindgen]
+pub fn propose_external_psk(
+    session_id: u32,
+    group_id: &[u8],
+    psk_id: &[u8],
+) -> Result<WasmProposalResult, MlsError> {
+    session::with_session(session_id, |s| {
+        let mut group = load_active_group(s, group_id)?;
+        let psk_id = psk::external(&s.provider, group.ciphersuite(), psk_id)?;
+
+        let (message, proposal_ref) = group
+            .propose_external_psk(&s.provider, &s.signer, psk_id)
+            .map_err(|e| MlsError::from_op("propose psk", e))?;
+        proposal_result(message, proposal_ref)
+    })
+}
+
+/// Create `group_id` as a branch of `parent_group_id`: a new group, with
+/// members drawn from the parent, whose first epoch mixes in the parent's
+/// current resumption secret. Joiners can only process the Welcome if they
+/// hold that epoch of the parent, which ties the branch to the parent's
+/// history. Like every commit, it stays pending until confirmed; the new
+/// group exists locally (at epoch 0) either way.
+#[wasm_bindgen]
+pub fn branch_group(
+    session_id: u32,
+    parent_group_id: &[u8],
+    group_id: &[u8],
+    key_packages: Vec<js_sys::Uint8Array>,
+    options: JsValue,
+) -> Result<WasmAddMemberResult, MlsError> {
+    let options: config::GroupOptions = config::parse(options, "group options")?;
+    session::with_session(session_id, |s| {
+        let parent = load_active_group(s, parent_group_id)?;
+        if key_packages.is_empty() {
+            return Err(MlsError::invalid_input("no key packages to add"));
+        }
+
+        let parent_keys: Vec<Vec<u8>> = parent.members().map(|m| m.signature_key).collect();
+        let mut seen_keys = vec![parent
+            .own_leaf_node()
+            .map_or_else(Vec::new, |leaf| leaf.signature_key().as_slice().to_vec())];
+        let mut packages = Vec::new();
+        for (index, bytes) in key_packages.iter().enumerate() {
+            let in_package = |e: MlsError| {
+                MlsError::new(e.code(), format!("key package {}: {}", index, e.message()))
+            };
+            let kp = validate_key_package(s, &bytes.to_vec()).map_err(in_package)?;
+            if kp.ciphersuite() != parent.ciphersuite() {
+                return Err(in_package(MlsError::new(
+                    ErrorCode::UnsupportedCiphersuite,
+                    "ciphersuite does not match the parent group's",
+                )));
+            }
+            let signature_key = kp.leaf_node().signature_key().as_slice().to_vec();
+            if !parent_keys.contains(&signature_key) {
+                return Err(in_package(MlsError::new(
+                    ErrorCode::MemberNotFound,
+                    "signature key is not a member of the parent group",
+                )));
+            }
+            if seen_keys.contains(&signature_key) {
+                return Err(in_package(MlsError::new(
+                    ErrorCode::InvalidKeyPackage,
+                    "signature key is ours or earlier in the batch",
+                )));
+            }
+            check_leaf_node(s, kp.leaf_node()).map_err(in_package)?;
+            seen_keys.push(signature_key);
+            packages.push(kp);
+        }
+
+        let builder = MlsGroup::builder()
+            .with_group_id(GroupId::from_slice(group_id))
+            .ciphersuite(parent.ciphersuite())
+            .use_ratchet_tree_extension(true);
+        let mut group = options
+            .apply(builder)?
+            .build(&s.provider, &s.signer, s.credential_with_key.clone())
+            .map_err(|e| MlsError::internal("create group", e))?;
+        record_self_update(s, &group)?;
+
+        // The parent's secret is only needed while the commit is built
+        let psk_id = psk::branch_id(parent.group_id(), parent.epoch())?;
+        let mut branch_psk = psk::TemporaryPsks::new(&s.provider);
+        branch_psk.store(psk_id.clone(), parent.resumption_psk_secret().as_slice())?;
+        let result = branch_commit(s, &mut group, packages, &psk_id);
+        drop(branch_psk);
+        match result {
+            Ok(result) => Ok(WasmAddMemberResult {
+                commit: result.commit,
+                welcome: result.welcome.unwrap_or_default(),
+                group_info: result.group_info,
             }),
+            Err(e) => {
+                // Don't leave a half-made branch behind
+                delete_group(s, &mut group)?;
+                Err(e.with_group(group_id))
+            }
         }
     })
-    .map_err(|e| JsError::new(&e))
+}
+
+fn branch_commit(
+    s: &session::Session,
+    group: &mut MlsGroup,
+    packages: Vec<KeyPackage>,
+    psk_id: &[u8],
+) -> Result<WasmCommitResult, MlsError> {
+    let psk_proposal =
+        PreSharedKeyProposal::new(psk::external(&s.provider, group.ciphersuite(), psk_id)?);
+    let bundle = group
+        .commit_builder()
+        .propose_adds(packages)
+        .add_proposal(Proposal::PreSharedKey(Box::new(psk_proposal)))
+        .load_psks(s.provider.storage())
+        .map_err(|e| MlsError::from_op("branch group", e))?
+        .build(s.provider.rand(), s.provider.crypto(), &s.signer, |_| true)
+        .map_err(|e| MlsError::from_op("branch group", e))?
+        .stage_commit(&s.provider)
+        .map_err(|e| MlsError::from_op("branch group", e))?;
+
+    let commit_out = bundle.commit().clone();
+    let welcome_out = bundle.to_welcome_msg();
+    commit_result(s, group, commit_out, welcome_out, bundle.into_group_info())
+}
+
+// Register a branch PSK for every epoch of `parent` we still hold the
+// resumption secret of, since a Welcome doesn't say which one it was branched
+// from until it is decrypted.
+fn register_branch_psks(
+    parent: &MlsGroup,
+    psks: &mut psk::TemporaryPsks<'_>,
+) -> Result<(), MlsError> {
+    // Past secrets are kept for the most recent epochs only, so walk back
+    // until one is missing
+    for epoch in (0..=parent.epoch().as_u64()).rev().map(GroupEpoch::from) {
+        let Some(secret) = psk::resumption_secret(parent, epoch) else {
+            break;
+        };
+        psks.store(psk::branch_id(parent.group_id(), epoch)?, &secret)?;
+    }
+    Ok(())
+}
+
+// ==================== Pending Commits ====================
+
+/// Apply our pending commit once the server has accepted it. Returns the
+/// commit details as JSON, in the same shape as for incoming commits.
+#[wasm_bindgen]
+pub fn confirm_pending_commit(session_id: u32, group_id: &[u8]) -> Result<String, MlsError> {
+    session::with_session(session_id, |s| {
+        let mut group = load_group(s, group_id)?;
+        let Some(pending) = group.pending_commit() else {
+            return Err(
+                MlsError::new(ErrorCode::NoPendingCommit, "no pending commit to confirm")
+                    .with_group(group_id),
+            );
+        };
+        let summary =
+            CommitSummary::stage(&group, pending, &Sender::Member(group.own_leaf_index()));
+        merge_own_commit(s, &mut group)?;
+        summary.finish(&group)
+    })
+}
+
+/// Drop our pending commit, e.g. after the server refused it. The group stays
+/// at its current epoch and queued proposals are kept. Does nothing if no
+/// commit is pending.
+#[wasm_bindgen]
+pub fn discard_pending_commit(session_id: u32, group_id: &[u8]) -> Result<(), MlsError> {
+    session::with_session(session_id, |s| {
+        let mut group = load_group(s, group_id)?;
+        group
+            .clear_pending_commit(s.provider.storage())
+            .map_err(|e| MlsError::storage("discard pending commit", e))
+    })
+}
+
+#[wasm_bindgen]
+pub fn has_pending_commit(session_id: u32, group_id: &[u8]) -> Result<bool, MlsError> {
+    session::with_session(session_id, |s| {
+        let group = load_group(s, group_id)?;
+        Ok(group.pending_commit().is_some())
+    })
 }
 
 // ==================== Group Inspection ====================
 
+/// False once we have left the group (see `leave_group`) or been removed
+/// from it.
 #[wasm_bindgen]
-pub fn get_epoch(session_id: u32, group_id: &[u8]) -> Result<u64, JsError> {
+pub fn is_group_active(session_id: u32, group_id: &[u8]) -> Result<bool, MlsError> {
+    session::with_session(session_id, |s| {
+        let group = load_group(s, group_id)?;
+        Ok(group.is_active() && !has_left(s, group_id)?)
+    })
+}
+
+#[wasm_bindgen]
+pub fn get_epoch(session_id: u32, group_id: &[u8]) -> Result<u64, MlsError> {
     session::with_session(session_id, |s| {
         let group = load_group(s, group_id)?;
         Ok(group.epoch().as_u64())
     })
-    .map_err(|e| JsError::new(&e))
 }
 
 #[wasm_bindgen]
-pub fn get_members(session_id: u32, group_id: &[u8]) -> Result<String, JsError> {
+pub fn epochs_since_self_update(session_id: u32, group_id: &[u8]) -> Result<u64, MlsError> {
     session::with_session(session_id, |s| {
         let group = load_group(s, group_id)?;
-        let members: Vec<serde_json::Value> = group
-            .members()
-            .map(|m| {
-                let identity = extract_identity(&m.credential);
-                serde_json::json!({
-                    "index": m.index.u32(),
-                    "identity": identity,
-                    "signature_key": m.signature_key,
-                })
-            })
+        let last: Option<u64> = s
+            .provider
+            .storage()
+            .read_meta(LAST_SELF_UPDATE_LABEL, group_id)
+            .map_err(|e| MlsError::storage("read self update", e))?;
+        Ok(group.epoch().as_u64().saturating_sub(last.unwrap_or(0)))
+    })
+}
+
+#[wasm_bindgen]
+pub fn export_secret(
+    session_id: u32,
+    group_id: &[u8],
+    label: &str,
+    context: &[u8],
+    length: usize,
+) -> Result<Vec<u8>, MlsError> {
+    if length == 0 {
+        return Err(MlsError::invalid_input(
+            "exported secret length must be non-zero",
+        ));
+    }
+    session::with_session(session_id, |s| {
+        let group = load_group(s, group_id)?;
+        group
+            .export_secret(s.provider.crypto(), label, context, length)
+            .map_err(|e| MlsError::from_op("export secret", e))
+    })
+}
+
+/// The current epoch's authenticator. Every member in this epoch of the
+/// group holds the same value, so comparing it out of band (see
+/// `group_fingerprint`) detects a server showing members different groups.
+#[wasm_bindgen]
+pub fn epoch_authenticator(session_id: u32, group_id: &[u8]) -> Result<Vec<u8>, MlsError> {
+    session::with_session(session_id, |s| {
+        let group = load_group(s, group_id)?;
+        Ok(group.epoch_authenticator().as_slice().to_vec())
+    })
+}
+
+/// Turn an epoch authenticator into a code people can compare: `"numeric"`
+/// (default) gives six blocks of five digits, `"emoji"` eight emoji. Both
+/// sides must be in the same epoch to get the same code.
+#[wasm_bindgen]
+pub fn group_fingerprint(
+    epoch_authenticator: &[u8],
+    format: Option<String>,
+) -> Result<String, MlsError> {
+    let format = fingerprint::Format::parse(format.as_deref())?;
+    fingerprint::fingerprint(epoch_authenticator, format)
+}
+
+#[wasm_bindgen]
+pub fn export_group_info(
+    session_id: u32,
+    group_id: &[u8],
+    with_ratchet_tree: bool,
+) -> Result<Vec<u8>, MlsError> {
+    session::with_session(session_id, |s| {
+        let group = load_group(s, group_id)?;
+        group
+            .export_group_info(s.provider.crypto(), &s.signer, with_ratchet_tree)
+            .map_err(|e| MlsError::from_op("export group info", e))?
+            .tls_serialize_detached()
+            .map_err(|e| MlsError::internal("serialize group info", e))
+    })
+}
+
+#[wasm_bindgen]
+pub fn export_ratchet_tree(session_id: u32, group_id: &[u8]) -> Result<Vec<u8>, MlsError> {
+    session::with_session(session_id, |s| {
+        let group = load_group(s, group_id)?;
+        group
+            .export_ratchet_tree()
+            .tls_serialize_detached()
+            .map_err(|e| MlsError::internal("serialize ratchet tree", e))
+    })
+}
+
+/// The group's members as JSON, one entry per user:
+/// `[{identity, did, home_server, devices: [{index, signature_key,
+/// device_id, device_label}]}]`. Leaves sharing an identity key are the same
+/// user's devices.
+#[wasm_bindgen]
+pub fn get_members(session_id: u32, group_id: &[u8]) -> Result<String, MlsError> {
+    session::with_session(session_id, |s| {
+        let group = load_group(s, group_id)?;
+        let users: Vec<serde_json::Value> = group_users(&group)?
+            .into_iter()
+            .map(|(_, user)| user)
             .collect();
-        serde_json::to_string(&members).map_err(|e| format!("serialize members: {}", e))
+        serde_json::to_string(&users).map_err(|e| MlsError::internal("serialize members", e))
     })
-    .map_err(|e| JsError::new(&e))
+}
+
+/// The user with `identity` in the same JSON shape as a `get_members` entry,
+/// or `null` if the identity is not in the group.
+#[wasm_bindgen]
+pub fn find_member(session_id: u32, group_id: &[u8], identity: &[u8]) -> Result<String, MlsError> {
+    session::with_session(session_id, |s| {
+        let group = load_group(s, group_id)?;
+        let user = group_users(&group)?
+            .into_iter()
+            .find(|(user_identity, _)| user_identity == identity)
+            .map(|(_, user)| user);
+        serde_json::to_string(&user).map_err(|e| MlsError::internal("serialize member", e))
+    })
+}
+
+// Members grouped by identity key, in order of each user's first leaf. The
+// user's DID and home server come from that first leaf.
+fn group_users(group: &MlsGroup) -> Result<Vec<(Vec<u8>, serde_json::Value)>, MlsError> {
+    let mut users: Vec<(Vec<u8>, serde_json::Value)> = Vec::new();
+    for member in group.members() {
+        let identity = extract_identity(&member.credential);
+        let cairn = cairn_identity(&member.credential)?;
+        let device = cairn.as_ref().and_then(|c| c.device.as_ref());
+        let device_json = serde_json::json!({
+            "index": member.index.u32(),
+            "signature_key": member.signature_key,
+            "device_id": device.map(|d| &d.id),
+            "device_label": device.map(|d| &d.label),
+        });
+        match users.iter_mut().find(|(i, _)| *i == identity) {
+            Some((_, user)) => {
+                if let Some(devices) = user["devices"].as_array_mut() {
+                    devices.push(device_json);
+                }
+            }
+            None => {
+                let user = serde_json::json!({
+                    "identity": identity,
+                    "did": cairn.as_ref().map(|c| &c.did),
+                    "home_server": cairn.as_ref().map(|c| &c.home_server),
+                    "devices": [device_json],
+                });
+                users.push((identity, user));
+            }
+        }
+    }
+    Ok(users)
 }
@@ -0,0 +1,141 @@
+//! Encrypted session snapshots.
+//!
+//! A snapshot holds everything needed to rebuild a `Session` after a reload:
+//! the provider's key/value storage (groups, epoch secrets, key packages),
+//! the signer and the credential. The serialized snapshot is sealed with
+//! ChaCha20-Poly1305 under a caller-supplied 32-byte key.
+//!
+//! Blob layout: `magic (4) || version (1) || nonce (12) || ciphertext`.
+//! The header is bound to the ciphertext as associated data.
+
+use openmls::prelude::*;
+use openmls_basic_credential::SignatureKeyPair;
+use openmls_rust_crypto::RustCrypto;
+use openmls_traits::crypto::OpenMlsCrypto;
+use openmls_traits::random::OpenMlsRand;
+use openmls_traits::types::AeadType;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{ErrorCode, MlsError};
+use crate::session::Session;
+use crate::storage::{SessionProvider, SessionStorage};
+
+const MAGIC: &[u8; 4] = b"CMLS";
+const SNAPSHOT_VERSION: u8 = 1;
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+const HEADER_LEN: usize = MAGIC.len() + 1;
+
+#[derive(Serialize)]
+struct SessionSnapshotRef<'a> {
+    identity: &'a [u8],
+    signer: &'a SignatureKeyPair,
+    credential_with_key: &'a CredentialWithKey,
+    ciphersuite: Ciphersuite,
+    storage: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+#[derive(Deserialize)]
+struct SessionSnapshot {
+    identity: Vec<u8>,
+    signer: SignatureKeyPair,
+    credential_with_key: CredentialWithKey,
+    ciphersuite: Ciphersuite,
+    storage: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+fn header() -> Vec<u8> {
+    let mut header = MAGIC.to_vec();
+    header.push(SNAPSHOT_VERSION);
+    header
+}
+
+fn check_key(key: &[u8]) -> Result<(), MlsError> {
+    if key.len() != KEY_LEN {
+        return Err(MlsError::invalid_input(format!(
+            "snapshot key must be {} bytes, got {}",
+            KEY_LEN,
+            key.len()
+        )));
+    }
+    Ok(())
+}
+
+pub fn export_session(session: &Session, key: &[u8]) -> Result<Vec<u8>, MlsError> {
+    check_key(key)?;
+
+    let storage = session.provider.storage().entries().ok_or_else(|| {
+        MlsError::new(
+            ErrorCode::Unsupported,
+            "session state is held by an external storage backend and cannot be exported",
+        )
+    })?;
+
+    let snapshot = SessionSnapshotRef {
+        identity: &session.identity,
+        signer: &session.signer,
+        credential_with_key: &session.credential_with_key,
+        ciphersuite: session.ciphersuite,
+        storage,
+    };
+    let plaintext =
+        serde_json::to_vec(&snapshot).map_err(|e| MlsError::internal("serialize snapshot", e))?;
+
+    let crypto = session.provider.crypto();
+    let nonce = session
+        .provider
+        .rand()
+        .random_vec(NONCE_LEN)
+        .map_err(|e| MlsError::internal("generate nonce", e))?;
+    let header = header();
+    let ciphertext = crypto
+        .aead_encrypt(AeadType::ChaCha20Poly1305, key, &plaintext, &nonce, &header)
+        .map_err(|e| MlsError::internal("encrypt snapshot", e))?;
+
+    let mut blob = Vec::with_capacity(HEADER_LEN + NONCE_LEN + ciphertext.len());
+    blob.extend_from_slice(&header);
+    blob.extend_from_slice(&nonce);
+    blob.extend_from_slice(&ciphertext);
+    Ok(blob)
+}
+
+pub fn import_session(blob: &[u8], key: &[u8]) -> Result<Session, MlsError> {
+    check_key(key)?;
+
+    if blob.len() < HEADER_LEN + NONCE_LEN || &blob[..MAGIC.len()] != MAGIC {
+        return Err(MlsError::new(
+            ErrorCode::InvalidSnapshot,
+            "not a session snapshot",
+        ));
+    }
+    let version = blob[MAGIC.len()];
+    if version != SNAPSHOT_VERSION {
+        return Err(MlsError::new(
+            ErrorCode::InvalidSnapshot,
+            format!("unsupported snapshot version {}", version),
+        ));
+    }
+
+    let (header, rest) = blob.split_at(HEADER_LEN);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+    let plaintext = RustCrypto::default()
+        .aead_decrypt(AeadType::ChaCha20Poly1305, key, ciphertext, nonce, header)
+        .map_err(|_| {
+            MlsError::new(
+                ErrorCode::InvalidSnapshot,
+                "decrypt snapshot: wrong key or corrupted data",
+            )
+        })?;
+
+    let snapshot: SessionSnapshot = serde_json::from_slice(&plaintext)
+        .map_err(|e| MlsError::new(ErrorCode::InvalidSnapshot, format!("parse snapshot: {}", e)))?;
+
+    Ok(Session {
+        provider: SessionProvider::new(SessionStorage::from_entries(snapshot.storage)),
+        signer: snapshot.signer,
+        credential_with_key: snapshot.credential_with_key,
+        ciphersuite: snapshot.ciphersuite,
+        identity: snapshot.identity,
+        credential_validator: None,
+    })
+}
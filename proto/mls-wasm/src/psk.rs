@@ -0,0 +1,121 @@
+//! Pre-shared keys mixed into a group's key schedule.
+//!
+//! External PSKs are secrets the application distributes itself; every
+//! member registers them in its session under the same id before a commit
+//! (or Welcome) that uses them can be processed.
+//!
+//! Branching binds a new group to its parent's history with the parent's
+//! resumption secret. openmls only resolves resumption PSKs from a group's
+//! own past epochs, so the parent's secret travels as an external PSK whose
+//! id names the parent group and epoch. Joiners supply it from their own
+//! copy of the parent when they process the Welcome.
+
+use openmls::prelude::*;
+use openmls::schedule::{ExternalPsk, PreSharedKeyId, Psk};
+use openmls_traits::storage::StorageProvider;
+use openmls_traits::OpenMlsProvider;
+use tls_codec::{Serialize as TlsSerializeTrait, TlsSerialize, TlsSize, VLBytes};
+
+use crate::error::MlsError;
+use crate::storage::SessionProvider;
+
+const BRANCH_LABEL: &[u8] = b"Cairn branch PSK v1";
+
+#[derive(TlsSerialize, TlsSize)]
+struct BranchPskId {
+    label: VLBytes,
+    group_id: VLBytes,
+    epoch: u64,
+}
+
+/// The external PSK id standing for `group_id`'s resumption secret at `epoch`.
+pub fn branch_id(group_id: &GroupId, epoch: GroupEpoch) -> Result<Vec<u8>, MlsError> {
+    BranchPskId {
+        label: BRANCH_LABEL.into(),
+        group_id: group_id.as_slice().into(),
+        epoch: epoch.as_u64(),
+    }
+    .tls_serialize_detached()
+    .map_err(|e| MlsError::internal("encode branch psk id", e))
+}
+
+/// `group`'s resumption secret at `epoch`, if it still holds it.
+pub fn resumption_secret(group: &MlsGroup, epoch: GroupEpoch) -> Option<Vec<u8>> {
+    if epoch == group.epoch() {
+        return Some(group.resumption_psk_secret().as_slice().to_vec());
+    }
+    group
+        .get_past_resumption_psk(epoch)
+        .map(|secret| secret.as_slice().to_vec())
+}
+
+/// A reference to the external PSK `psk_id` with a fresh nonce, for use in
+/// a proposal.
+pub fn external(
+    provider: &SessionProvider,
+    ciphersuite: Ciphersuite,
+    psk_id: &[u8],
+) -> Result<PreSharedKeyId, MlsError> {
+    PreSharedKeyId::new(
+        ciphersuite,
+        provider.rand(),
+        Psk::External(ExternalPsk::new(psk_id.to_vec())),
+    )
+    .map_err(|e| MlsError::internal("create psk id", e))
+}
+
+pub fn store(provider: &SessionProvider, psk_id: &[u8], secret: &[u8]) -> Result<(), MlsError> {
+    if psk_id.is_empty() || secret.is_empty() {
+        return Err(MlsError::invalid_input(
+            "psk id and secret must not be empty",
+        ));
+    }
+    // The nonce isn't part of the storage key
+    PreSharedKeyId::external(psk_id.to_vec(), vec![])
+        .store(provider, secret)
+        .map_err(|e| MlsError::storage("store psk", e))
+}
+
+pub fn delete(provider: &SessionProvider, psk_id: &[u8]) -> Result<(), MlsError> {
+    provider
+        .storage()
+        .delete_psk(&Psk::External(ExternalPsk::new(psk_id.to_vec())))
+        .map_err(|e| MlsError::storage("delete psk", e))
+}
+
+/// PSKs stored for the span of one operation. They are deleted again when
+/// the guard is dropped, however the operation ends, so a parent's
+/// resumption secret never outlives the branch it was lent to.
+pub struct TemporaryPsks<'a> {
+    provider: &'a SessionProvider,
+    ids: Vec<Vec<u8>>,
+}
+
+impl<'a> TemporaryPsks<'a> {
+    pub fn new(provider: &'a SessionProvider) -> Self {
+        TemporaryPsks {
+            provider,
+            ids: Vec::new(),
+        }
+    }
+
+    pub fn store(&mut self, psk_id: Vec<u8>, secret: &[u8]) -> Result<(), MlsError> {
+        store(self.provider, &psk_id, secret)?;
+        self.ids.push(psk_id);
+        Ok(())
+    }
+
+    pub fn contains(&self, psk_id: &[u8]) -> bool {
+        self.ids.iter().any(|id| id == psk_id)
+    }
+}
+
+impl Drop for TemporaryPsks<'_> {
+    fn drop(&mut self) {
+        // Writes are buffered until the operation ends, so this can't fail
+        // halfway through
+        for psk_id in &self.ids {
+            let _ = delete(self.provider, psk_id);
+        }
+    }
+}
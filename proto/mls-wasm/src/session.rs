@@ -3,14 +3,19 @@ use std::collections::HashMap;
 
 use openmls::prelude::*;
 use openmls_basic_credential::SignatureKeyPair;
-use openmls_rust_crypto::OpenMlsRustCrypto;
+
+use crate::error::{ErrorCode, MlsError};
+use crate::storage::{SessionProvider, SessionStorage};
 
 pub struct Session {
-    pub provider: OpenMlsRustCrypto,
+    pub provider: SessionProvider,
     pub signer: SignatureKeyPair,
     pub credential_with_key: CredentialWithKey,
-    #[allow(dead_code)]
+    pub ciphersuite: Ciphersuite,
     pub identity: Vec<u8>,
+    /// JS callback that vets credentials entering a group. Not persisted;
+    /// callers re-register it after `import_session`.
+    pub credential_validator: Option<js_sys::Function>,
 }
 
 thread_local! {
@@ -22,47 +27,99 @@ pub fn new_session(
     identity: Vec<u8>,
     signer: SignatureKeyPair,
     credential_with_key: CredentialWithKey,
-) -> Result<u32, String> {
-    let id = NEXT_ID.with(|cell| {
-        let mut next = cell.borrow_mut();
-        let id = *next;
-        *next += 1;
-        id
-    });
-
-    let provider = OpenMlsRustCrypto::default();
+    ciphersuite: Ciphersuite,
+    storage: SessionStorage,
+) -> Result<u32, MlsError> {
+    let provider = SessionProvider::new(storage);
     signer
         .store(provider.storage())
-        .map_err(|e| format!("failed to store signer: {:?}", e))?;
+        .map_err(|e| MlsError::storage("store signer", e))?;
+    provider
+        .storage()
+        .commit()
+        .map_err(|e| MlsError::storage("commit storage", e))?;
 
-    SESSIONS.with(|cell| {
-        cell.borrow_mut().insert(
-            id,
-            Session {
-                provider,
-                signer,
-                credential_with_key,
-                identity,
-            },
-        );
-    });
+    insert_session(Session {
+        provider,
+        signer,
+        credential_with_key,
+        ciphersuite,
+        identity,
+        credential_validator: None,
+    })
+}
 
-    Ok(id)
+pub fn insert_session(session: Session) -> Result<u32, MlsError> {
+    with_sessions(|sessions| {
+        let id = NEXT_ID.with(|cell| {
+            let mut next = cell.borrow_mut();
+            let id = *next;
+            *next += 1;
+            id
+        });
+        sessions.insert(id, session);
+        id
+    })
 }
 
-pub fn drop_session(id: u32) -> bool {
-    SESSIONS.with(|cell| cell.borrow_mut().remove(&id).is_some())
+pub fn drop_session(id: u32) -> Result<bool, MlsError> {
+    with_sessions(|sessions| sessions.remove(&id).is_some())
 }
 
-pub fn with_session<F, R>(id: u32, f: F) -> Result<R, String>
+// The session table is borrowed for the whole of an operation, including any
+// credential validator it calls out to. A validator that calls back into
+// mls-wasm gets an error here instead of a panic that would take down the
+// module.
+fn with_sessions<R>(f: impl FnOnce(&mut HashMap<u32, Session>) -> R) -> Result<R, MlsError> {
+    SESSIONS.with(|cell| {
+        let mut sessions = cell.try_borrow_mut().map_err(|_| {
+            MlsError::new(
+                ErrorCode::SessionBusy,
+                "mls-wasm sessions can't be used while an operation is running",
+            )
+        })?;
+        Ok(f(&mut sessions))
+    })
+}
+
+/// Run one operation on a session. Its storage writes are applied together
+/// if it succeeds and discarded if it fails.
+///
+/// If a JS store throws while the writes are applied, it is left holding
+/// only part of them. The operation fails with `storage` and the session is
+/// poisoned: every later call fails with `storage` too, and the caller has
+/// to destroy it and create a new session over a store it has restored (a
+/// store that only persists on `commit()` never saw the partial batch).
+pub fn with_session<F, R>(id: u32, f: F) -> Result<R, MlsError>
 where
-    F: FnOnce(&mut Session) -> Result<R, String>,
+    F: FnOnce(&mut Session) -> Result<R, MlsError>,
 {
-    SESSIONS.with(|cell| {
-        let mut sessions = cell.borrow_mut();
-        match sessions.get_mut(&id) {
-            Some(session) => f(session),
-            None => Err(format!("session {} not found", id)),
+    with_sessions(|sessions| {
+        let Some(session) = sessions.get_mut(&id) else {
+            return Err(MlsError::new(
+                ErrorCode::SessionNotFound,
+                format!("session {} not found", id),
+            ));
+        };
+        if session.provider.storage().is_poisoned() {
+            return Err(MlsError::new(
+                ErrorCode::Storage,
+                "session storage failed partway through a commit; reopen the session",
+            ));
         }
-    })
+        let result = f(session);
+        let storage = session.provider.storage();
+        match result {
+            Ok(value) => {
+                storage
+                    .commit()
+                    .map_err(|e| MlsError::storage("commit storage", e))?;
+                Ok(value)
+            }
+            Err(e) => {
+                storage.rollback();
+                Err(e)
+            }
+        }
+    })?
 }
@@ -0,0 +1,926 @@
+//! Session storage and crypto provider.
+//!
+//! `SessionStorage` implements openmls's `StorageProvider` over a flat
+//! key/value store, using the same key layout as `openmls_memory_storage`.
+//! Values live in memory unless the session was created with a JS storage
+//! object, in which case state is persisted to it as each operation happens.
+//!
+//! Writes are buffered for the duration of an operation and applied together
+//! once it succeeds (see `commit`); a failed operation leaves the store as it
+//! was. A JS store learns where each operation ends through its optional
+//! `commit()`, so it can persist the batch atomically. If the JS store throws
+//! while a batch is applied, the session is poisoned and must be reopened.
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::fmt;
+
+use openmls_rust_crypto::RustCrypto;
+use openmls_traits::storage::{traits, StorageProvider, CURRENT_VERSION};
+use openmls_traits::OpenMlsProvider;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+#[wasm_bindgen(typescript_custom_section)]
+const MLS_STORAGE_TS: &'static str = r#"
+/**
+ * Synchronous key/value store backing an MLS session. Keys and values are
+ * opaque bytes; `get` returns `undefined` for a missing key.
+ */
+export interface MlsStorage {
+  get(key: Uint8Array): Uint8Array | undefined;
+  put(key: Uint8Array, value: Uint8Array): void;
+  delete(key: Uint8Array): void;
+  commit?(): void;
+}
+"#;
+
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(typescript_type = "MlsStorage")]
+    pub type JsStorage;
+
+    #[wasm_bindgen(method, catch)]
+    fn get(this: &JsStorage, key: &[u8]) -> Result<Option<Vec<u8>>, JsValue>;
+
+    #[wasm_bindgen(method, catch)]
+    fn put(this: &JsStorage, key: &[u8], value: &[u8]) -> Result<(), JsValue>;
+
+    #[wasm_bindgen(method, catch)]
+    fn delete(this: &JsStorage, key: &[u8]) -> Result<(), JsValue>;
+}
+
+#[derive(Debug)]
+pub enum StorageError {
+    Serialization(String),
+    Backend(String),
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageError::Serialization(e) => write!(f, "storage serialization: {}", e),
+            StorageError::Backend(e) => write!(f, "storage backend: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for StorageError {}
+
+impl From<serde_json::Error> for StorageError {
+    fn from(e: serde_json::Error) -> Self {
+        StorageError::Serialization(e.to_string())
+    }
+}
+
+impl From<JsValue> for StorageError {
+    fn from(e: JsValue) -> Self {
+        StorageError::Backend(format!("{:?}", e))
+    }
+}
+
+enum Backend {
+    Memory(RefCell<HashMap<Vec<u8>, Vec<u8>>>),
+    Js(JsStorage),
+}
+
+pub struct SessionStorage {
+    backend: Backend,
+    // Writes of the operation in progress; `None` marks a delete
+    pending: RefCell<HashMap<Vec<u8>, Option<Vec<u8>>>>,
+    // Set when a JS store failed partway through a commit
+    poisoned: Cell<bool>,
+}
+
+impl SessionStorage {
+    pub fn in_memory() -> Self {
+        Self::from_entries(Vec::new())
+    }
+
+    pub fn from_entries(entries: Vec<(Vec<u8>, Vec<u8>)>) -> Self {
+        SessionStorage {
+            backend: Backend::Memory(RefCell::new(entries.into_iter().collect())),
+            pending: RefCell::default(),
+            poisoned: Cell::default(),
+        }
+    }
+
+    pub fn with_js(storage: JsStorage) -> Self {
+        SessionStorage {
+            backend: Backend::Js(storage),
+            pending: RefCell::default(),
+            poisoned: Cell::default(),
+        }
+    }
+
+    /// All stored entries, or `None` when the state lives in a JS store.
+    pub fn entries(&self) -> Option<Vec<(Vec<u8>, Vec<u8>)>> {
+        match &self.backend {
+            Backend::Memory(values) => {
+                let mut entries = values.borrow().clone();
+                for (key, value) in self.pending.borrow().iter() {
+                    match value {
+                        Some(value) => entries.insert(key.clone(), value.clone()),
+                        None => entries.remove(key),
+                    };
+                }
+                Some(entries.into_iter().collect())
+            }
+            Backend::Js(_) => None,
+        }
+    }
+
+    /// Apply the writes buffered since the last commit, then let a JS store
+    /// know the operation is complete. If the JS store throws partway, it
+    /// holds only some of the writes, so the storage is poisoned: see
+    /// `is_poisoned`.
+    pub fn commit(&self) -> Result<(), StorageError> {
+        let pending = self.pending.take();
+        if pending.is_empty() {
+            return Ok(());
+        }
+        match &self.backend {
+            Backend::Memory(values) => {
+                let mut values = values.borrow_mut();
+                for (key, value) in pending {
+                    match value {
+                        Some(value) => values.insert(key, value),
+                        None => values.remove(&key),
+                    };
+                }
+            }
+            Backend::Js(js) => {
+                let flushed = (|| -> Result<(), JsValue> {
+                    for (key, value) in pending {
+                        match value {
+                            Some(value) => js.put(&key, &value)?,
+                            None => js.delete(&key)?,
+                        }
+                    }
+                    let commit = js_sys::Reflect::get(js, &"commit".into())?;
+                    if let Some(commit) = commit.dyn_ref::<js_sys::Function>() {
+                        commit.call0(js)?;
+                    }
+                    Ok(())
+                })();
+                if flushed.is_err() {
+                    self.poisoned.set(true);
+                }
+                flushed?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether a commit to the JS store failed partway. The store no longer
+    /// matches any state the session went through, so the session refuses
+    /// further operations and has to be reopened over the store.
+    pub fn is_poisoned(&self) -> bool {
+        self.poisoned.get()
+    }
+
+    /// Drop the writes buffered since the last commit.
+    pub fn rollback(&self) {
+        self.pending.borrow_mut().clear();
+    }
+
+    fn get_raw(&self, key: &[u8]) -> Result<Option<Vec<u8>>, StorageError> {
+        if let Some(value) = self.pending.borrow().get(key) {
+            return Ok(value.clone());
+        }
+        match &self.backend {
+            Backend::Memory(values) => Ok(values.borrow().get(key).cloned()),
+            Backend::Js(js) => Ok(js.get(key)?),
+        }
+    }
+
+    fn put_raw(&self, key: Vec<u8>, value: Vec<u8>) -> Result<(), StorageError> {
+        self.pending.borrow_mut().insert(key, Some(value));
+        Ok(())
+    }
+
+    fn delete_raw(&self, key: &[u8]) -> Result<(), StorageError> {
+        self.pending.borrow_mut().insert(key.to_vec(), None);
+        Ok(())
+    }
+
+    /// Write Cairn's own bookkeeping next to the openmls state, so it is
+    /// persisted and exported together with the groups it describes.
+    pub fn write_meta<V: Serialize>(
+        &self,
+        label: &[u8],
+        key: &[u8],
+        value: &V,
+    ) -> Result<(), StorageError> {
+        self.write(storage_key(label, key), value)
+    }
+
+    pub fn read_meta<V: DeserializeOwned>(
+        &self,
+        label: &[u8],
+        key: &[u8],
+    ) -> Result<Option<V>, StorageError> {
+        self.read(storage_key(label, key))
+    }
+
+    pub fn delete_meta(&self, label: &[u8], key: &[u8]) -> Result<(), StorageError> {
+        self.delete(storage_key(label, key))
+    }
+
+    fn write<V: Serialize>(&self, key: Vec<u8>, value: &V) -> Result<(), StorageError> {
+        self.put_raw(key, serde_json::to_vec(value)?)
+    }
+
+    fn read<V: DeserializeOwned>(&self, key: Vec<u8>) -> Result<Option<V>, StorageError> {
+        match self.get_raw(&key)? {
+            Some(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn delete(&self, key: Vec<u8>) -> Result<(), StorageError> {
+        self.delete_raw(&key)
+    }
+
+    fn read_raw_list(&self, key: &[u8]) -> Result<Vec<Vec<u8>>, StorageError> {
+        match self.get_raw(key)? {
+            Some(bytes) => Ok(serde_json::from_slice(&bytes)?),
+            None => Ok(vec![]),
+        }
+    }
+
+    fn read_list<V: DeserializeOwned>(&self, key: Vec<u8>) -> Result<Vec<V>, StorageError> {
+        self.read_raw_list(&key)?
+            .iter()
+            .map(|item| serde_json::from_slice(item).map_err(StorageError::from))
+            .collect()
+    }
+
+    fn append<V: Serialize>(&self, key: Vec<u8>, value: &V) -> Result<(), StorageError> {
+        let mut list = self.read_raw_list(&key)?;
+        list.push(serde_json::to_vec(value)?);
+        self.write(key, &list)
+    }
+
+    fn remove_item<V: Serialize>(&self, key: Vec<u8>, value: &V) -> Result<(), StorageError> {
+        let value = serde_json::to_vec(value)?;
+        let mut list = self.read_raw_list(&key)?;
+        if let Some(pos) = list.iter().position(|item| item == &value) {
+            list.remove(pos);
+        }
+        self.write(key, &list)
+    }
+}
+
+/// Storage key layout shared with `openmls_memory_storage`:
+/// `label || key || version`.
+fn storage_key(label: &[u8], key: &[u8]) -> Vec<u8> {
+    let mut out = label.to_vec();
+    out.extend_from_slice(key);
+    out.extend_from_slice(&CURRENT_VERSION.to_be_bytes());
+    out
+}
+
+fn key_of<K: Serialize>(label: &[u8], key: &K) -> Result<Vec<u8>, StorageError> {
+    Ok(storage_key(label, &serde_json::to_vec(key)?))
+}
+
+fn epoch_key_pairs_id(
+    group_id: &impl traits::GroupId<CURRENT_VERSION>,
+    epoch: &impl traits::EpochKey<CURRENT_VERSION>,
+    leaf_index: u32,
+) -> Result<Vec<u8>, StorageError> {
+    let mut key = serde_json::to_vec(group_id)?;
+    key.extend_from_slice(&serde_json::to_vec(epoch)?);
+    key.extend_from_slice(&serde_json::to_vec(&leaf_index)?);
+    Ok(key)
+}
+
+// Cairn bookkeeping, keyed by raw group id
+pub const LAST_SELF_UPDATE_LABEL: &[u8] = b"CairnLastSelfUpdate";
+pub const LEFT_GROUP_LABEL: &[u8] = b"CairnLeftGroup";
+// Session-wide Cairn bookkeeping, keyed by the empty string
+pub const USED_KEY_PACKAGES_LABEL: &[u8] = b"CairnUsedKeyPackages";
+
+const KEY_PACKAGE_LABEL: &[u8] = b"KeyPackage";
+const PSK_LABEL: &[u8] = b"Psk";
+const ENCRYPTION_KEY_PAIR_LABEL: &[u8] = b"EncryptionKeyPair";
+const SIGNATURE_KEY_PAIR_LABEL: &[u8] = b"SignatureKeyPair";
+const EPOCH_KEY_PAIRS_LABEL: &[u8] = b"EpochKeyPairs";
+
+const TREE_LABEL: &[u8] = b"Tree";
+const GROUP_CONTEXT_LABEL: &[u8] = b"GroupContext";
+const INTERIM_TRANSCRIPT_HASH_LABEL: &[u8] = b"InterimTranscriptHash";
+const CONFIRMATION_TAG_LABEL: &[u8] = b"ConfirmationTag";
+
+const JOIN_CONFIG_LABEL: &[u8] = b"MlsGroupJoinConfig";
+const OWN_LEAF_NODES_LABEL: &[u8] = b"OwnLeafNodes";
+const GROUP_STATE_LABEL: &[u8] = b"GroupState";
+const QUEUED_PROPOSAL_LABEL: &[u8] = b"QueuedProposal";
+const PROPOSAL_QUEUE_REFS_LABEL: &[u8] = b"ProposalQueueRefs";
+const OWN_LEAF_NODE_INDEX_LABEL: &[u8] = b"OwnLeafNodeIndex";
+const EPOCH_SECRETS_LABEL: &[u8] = b"EpochSecrets";
+const RESUMPTION_PSK_STORE_LABEL: &[u8] = b"ResumptionPsk";
+const MESSAGE_SECRETS_LABEL: &[u8] = b"MessageSecrets";
+
+impl StorageProvider<CURRENT_VERSION> for SessionStorage {
+    type Error = StorageError;
+
+    // ---- group state writers ----
+
+    fn write_mls_join_config<
+        GroupId: traits::GroupId<CURRENT_VERSION>,
+        MlsGroupJoinConfig: traits::MlsGroupJoinConfig<CURRENT_VERSION>,
+    >(
+        &self,
+        group_id: &GroupId,
+        config: &MlsGroupJoinConfig,
+    ) -> Result<(), Self::Error> {
+        self.write(key_of(JOIN_CONFIG_LABEL, group_id)?, config)
+    }
+
+    fn append_own_leaf_node<
+        GroupId: traits::GroupId<CURRENT_VERSION>,
+        LeafNode: traits::LeafNode<CURRENT_VERSION>,
+    >(
+        &self,
+        group_id: &GroupId,
+        leaf_node: &LeafNode,
+    ) -> Result<(), Self::Error> {
+        self.append(key_of(OWN_LEAF_NODES_LABEL, group_id)?, leaf_node)
+    }
+
+    fn queue_proposal<
+        GroupId: traits::GroupId<CURRENT_VERSION>,
+        ProposalRef: traits::ProposalRef<CURRENT_VERSION>,
+        QueuedProposal: traits::QueuedProposal<CURRENT_VERSION>,
+    >(
+        &self,
+        group_id: &GroupId,
+        proposal_ref: &ProposalRef,
+        proposal: &QueuedProposal,
+    ) -> Result<(), Self::Error> {
+        self.write(
+            key_of(QUEUED_PROPOSAL_LABEL, &(group_id, proposal_ref))?,
+            proposal,
+        )?;
+        self.append(key_of(PROPOSAL_QUEUE_REFS_LABEL, group_id)?, proposal_ref)
+    }
+
+    fn write_tree<
+        GroupId: traits::GroupId<CURRENT_VERSION>,
+        TreeSync: traits::TreeSync<CURRENT_VERSION>,
+    >(
+        &self,
+        group_id: &GroupId,
+        tree: &TreeSync,
+    ) -> Result<(), Self::Error> {
+        self.write(key_of(TREE_LABEL, group_id)?, tree)
+    }
+
+    fn write_interim_transcript_hash<
+        GroupId: traits::GroupId<CURRENT_VERSION>,
+        InterimTranscriptHash: traits::InterimTranscriptHash<CURRENT_VERSION>,
+    >(
+        &self,
+        group_id: &GroupId,
+        interim_transcript_hash: &InterimTranscriptHash,
+    ) -> Result<(), Self::Error> {
+        self.write(
+            key_of(INTERIM_TRANSCRIPT_HASH_LABEL, group_id)?,
+            interim_transcript_hash,
+        )
+    }
+
+    fn write_context<
+        GroupId: traits::GroupId<CURRENT_VERSION>,
+        GroupContext: traits::GroupContext<CURRENT_VERSION>,
+    >(
+        &self,
+        group_id: &GroupId,
+        group_context: &GroupContext,
+    ) -> Result<(), Self::Error> {
+        self.write(key_of(GROUP_CONTEXT_LABEL, group_id)?, group_context)
+    }
+
+    fn write_confirmation_tag<
+        GroupId: traits::GroupId<CURRENT_VERSION>,
+        ConfirmationTag: traits::ConfirmationTag<CURRENT_VERSION>,
+    >(
+        &self,
+        group_id: &GroupId,
+        confirmation_tag: &ConfirmationTag,
+    ) -> Result<(), Self::Error> {
+        self.write(key_of(CONFIRMATION_TAG_LABEL, group_id)?, confirmation_tag)
+    }
+
+    fn write_group_state<
+        GroupState: traits::GroupState<CURRENT_VERSION>,
+        GroupId: traits::GroupId<CURRENT_VERSION>,
+    >(
+        &self,
+        group_id: &GroupId,
+        group_state: &GroupState,
+    ) -> Result<(), Self::Error> {
+        self.write(key_of(GROUP_STATE_LABEL, group_id)?, group_state)
+    }
+
+    fn write_message_secrets<
+        GroupId: traits::GroupId<CURRENT_VERSION>,
+        MessageSecrets: traits::MessageSecrets<CURRENT_VERSION>,
+    >(
+        &self,
+        group_id: &GroupId,
+        message_secrets: &MessageSecrets,
+    ) -> Result<(), Self::Error> {
+        self.write(key_of(MESSAGE_SECRETS_LABEL, group_id)?, message_secrets)
+    }
+
+    fn write_resumption_psk_store<
+        GroupId: traits::GroupId<CURRENT_VERSION>,
+        ResumptionPskStore: traits::ResumptionPskStore<CURRENT_VERSION>,
+    >(
+        &self,
+        group_id: &GroupId,
+        resumption_psk_store: &ResumptionPskStore,
+    ) -> Result<(), Self::Error> {
+        self.write(
+            key_of(RESUMPTION_PSK_STORE_LABEL, group_id)?,
+            resumption_psk_store,
+        )
+    }
+
+    fn write_own_leaf_index<
+        GroupId: traits::GroupId<CURRENT_VERSION>,
+        LeafNodeIndex: traits::LeafNodeIndex<CURRENT_VERSION>,
+    >(
+        &self,
+        group_id: &GroupId,
+        own_leaf_index: &LeafNodeIndex,
+    ) -> Result<(), Self::Error> {
+        self.write(key_of(OWN_LEAF_NODE_INDEX_LABEL, group_id)?, own_leaf_index)
+    }
+
+    fn write_group_epoch_secrets<
+        GroupId: traits::GroupId<CURRENT_VERSION>,
+        GroupEpochSecrets: traits::GroupEpochSecrets<CURRENT_VERSION>,
+    >(
+        &self,
+        group_id: &GroupId,
+        group_epoch_secrets: &GroupEpochSecrets,
+    ) -> Result<(), Self::Error> {
+        self.write(key_of(EPOCH_SECRETS_LABEL, group_id)?, group_epoch_secrets)
+    }
+
+    // ---- crypto object writers ----
+
+    fn write_signature_key_pair<
+        SignaturePublicKey: traits::SignaturePublicKey<CURRENT_VERSION>,
+        SignatureKeyPair: traits::SignatureKeyPair<CURRENT_VERSION>,
+    >(
+        &self,
+        public_key: &SignaturePublicKey,
+        signature_key_pair: &SignatureKeyPair,
+    ) -> Result<(), Self::Error> {
+        self.write(
+            key_of(SIGNATURE_KEY_PAIR_LABEL, public_key)?,
+            signature_key_pair,
+        )
+    }
+
+    fn write_encryption_key_pair<
+        EncryptionKey: traits::EncryptionKey<CURRENT_VERSION>,
+        HpkeKeyPair: traits::HpkeKeyPair<CURRENT_VERSION>,
+    >(
+        &self,
+        public_key: &EncryptionKey,
+        key_pair: &HpkeKeyPair,
+    ) -> Result<(), Self::Error> {
+        self.write(key_of(ENCRYPTION_KEY_PAIR_LABEL, public_key)?, key_pair)
+    }
+
+    fn write_encryption_epoch_key_pairs<
+        GroupId: traits::GroupId<CURRENT_VERSION>,
+        EpochKey: traits::EpochKey<CURRENT_VERSION>,
+        HpkeKeyPair: traits::HpkeKeyPair<CURRENT_VERSION>,
+    >(
+        &self,
+        group_id: &GroupId,
+        epoch: &EpochKey,
+        leaf_index: u32,
+        key_pairs: &[HpkeKeyPair],
+    ) -> Result<(), Self::Error> {
+        let key = storage_key(
+            EPOCH_KEY_PAIRS_LABEL,
+            &epoch_key_pairs_id(group_id, epoch, leaf_index)?,
+        );
+        self.write(key, &key_pairs)
+    }
+
+    fn write_key_package<
+        HashReference: traits::HashReference<CURRENT_VERSION>,
+        KeyPackage: traits::KeyPackage<CURRENT_VERSION>,
+    >(
+        &self,
+        hash_ref: &HashReference,
+        key_package: &KeyPackage,
+    ) -> Result<(), Self::Error> {
+        self.write(key_of(KEY_PACKAGE_LABEL, hash_ref)?, key_package)
+    }
+
+    fn write_psk<
+        PskId: traits::PskId<CURRENT_VERSION>,
+        PskBundle: traits::PskBundle<CURRENT_VERSION>,
+    >(
+        &self,
+        psk_id: &PskId,
+        psk: &PskBundle,
+    ) -> Result<(), Self::Error> {
+        self.write(key_of(PSK_LABEL, psk_id)?, psk)
+    }
+
+    // ---- group state readers ----
+
+    fn mls_group_join_config<
+        GroupId: traits::GroupId<CURRENT_VERSION>,
+        MlsGroupJoinConfig: traits::MlsGroupJoinConfig<CURRENT_VERSION>,
+    >(
+        &self,
+        group_id: &GroupId,
+    ) -> Result<Option<MlsGroupJoinConfig>, Self::Error> {
+        self.read(key_of(JOIN_CONFIG_LABEL, group_id)?)
+    }
+
+    fn own_leaf_nodes<
+        GroupId: traits::GroupId<CURRENT_VERSION>,
+        LeafNode: traits::LeafNode<CURRENT_VERSION>,
+    >(
+        &self,
+        group_id: &GroupId,
+    ) -> Result<Vec<LeafNode>, Self::Error> {
+        self.read_list(key_of(OWN_LEAF_NODES_LABEL, group_id)?)
+    }
+
+    fn queued_proposal_refs<
+        GroupId: traits::GroupId<CURRENT_VERSION>,
+        ProposalRef: traits::ProposalRef<CURRENT_VERSION>,
+    >(
+        &self,
+        group_id: &GroupId,
+    ) -> Result<Vec<ProposalRef>, Self::Error> {
+        self.read_list(key_of(PROPOSAL_QUEUE_REFS_LABEL, group_id)?)
+    }
+
+    fn queued_proposals<
+        GroupId: traits::GroupId<CURRENT_VERSION>,
+        ProposalRef: traits::ProposalRef<CURRENT_VERSION>,
+        QueuedProposal: traits::QueuedProposal<CURRENT_VERSION>,
+    >(
+        &self,
+        group_id: &GroupId,
+    ) -> Result<Vec<(ProposalRef, QueuedProposal)>, Self::Error> {
+        let refs: Vec<ProposalRef> =
+            self.read_list(key_of(PROPOSAL_QUEUE_REFS_LABEL, group_id)?)?;
+        let mut proposals = Vec::with_capacity(refs.len());
+        for proposal_ref in refs {
+            let key = key_of(QUEUED_PROPOSAL_LABEL, &(group_id, &proposal_ref))?;
+            if let Some(proposal) = self.read(key)? {
+                proposals.push((proposal_ref, proposal));
+            }
+        }
+        Ok(proposals)
+    }
+
+    fn tree<
+        GroupId: traits::GroupId<CURRENT_VERSION>,
+        TreeSync: traits::TreeSync<CURRENT_VERSION>,
+    >(
+        &self,
+        group_id: &GroupId,
+    ) -> Result<Option<TreeSync>, Self::Error> {
+        self.read(key_of(TREE_LABEL, group_id)?)
+    }
+
+    fn group_context<
+        GroupId: traits::GroupId<CURRENT_VERSION>,
+        GroupContext: traits::GroupContext<CURRENT_VERSION>,
+    >(
+        &self,
+        group_id: &GroupId,
+    ) -> Result<Option<GroupContext>, Self::Error> {
+        self.read(key_of(GROUP_CONTEXT_LABEL, group_id)?)
+    }
+
+    fn interim_transcript_hash<
+        GroupId: traits::GroupId<CURRENT_VERSION>,
+        InterimTranscriptHash: traits::InterimTranscriptHash<CURRENT_VERSION>,
+    >(
+        &self,
+        group_id: &GroupId,
+    ) -> Result<Option<InterimTranscriptHash>, Self::Error> {
+        self.read(key_of(INTERIM_TRANSCRIPT_HASH_LABEL, group_id)?)
+    }
+
+    fn confirmation_tag<
+        GroupId: traits::GroupId<CURRENT_VERSION>,
+        ConfirmationTag: traits::ConfirmationTag<CURRENT_VERSION>,
+    >(
+        &self,
+        group_id: &GroupId,
+    ) -> Result<Option<ConfirmationTag>, Self::Error> {
+        self.read(key_of(CONFIRMATION_TAG_LABEL, group_id)?)
+    }
+
+    fn group_state<
+        GroupState: traits::GroupState<CURRENT_VERSION>,
+        GroupId: traits::GroupId<CURRENT_VERSION>,
+    >(
+        &self,
+        group_id: &GroupId,
+    ) -> Result<Option<GroupState>, Self::Error> {
+        self.read(key_of(GROUP_STATE_LABEL, group_id)?)
+    }
+
+    fn message_secrets<
+        GroupId: traits::GroupId<CURRENT_VERSION>,
+        MessageSecrets: traits::MessageSecrets<CURRENT_VERSION>,
+    >(
+        &self,
+        group_id: &GroupId,
+    ) -> Result<Option<MessageSecrets>, Self::Error> {
+        self.read(key_of(MESSAGE_SECRETS_LABEL, group_id)?)
+    }
+
+    fn resumption_psk_store<
+        GroupId: traits::GroupId<CURRENT_VERSION>,
+        ResumptionPskStore: traits::ResumptionPskStore<CURRENT_VERSION>,
+    >(
+        &self,
+        group_id: &GroupId,
+    ) -> Result<Option<ResumptionPskStore>, Self::Error> {
+        self.read(key_of(RESUMPTION_PSK_STORE_LABEL, group_id)?)
+    }
+
+    fn own_leaf_index<
+        GroupId: traits::GroupId<CURRENT_VERSION>,
+        LeafNodeIndex: traits::LeafNodeIndex<CURRENT_VERSION>,
+    >(
+        &self,
+        group_id: &GroupId,
+    ) -> Result<Option<LeafNodeIndex>, Self::Error> {
+        self.read(key_of(OWN_LEAF_NODE_INDEX_LABEL, group_id)?)
+    }
+
+    fn group_epoch_secrets<
+        GroupId: traits::GroupId<CURRENT_VERSION>,
+        GroupEpochSecrets: traits::GroupEpochSecrets<CURRENT_VERSION>,
+    >(
+        &self,
+        group_id: &GroupId,
+    ) -> Result<Option<GroupEpochSecrets>, Self::Error> {
+        self.read(key_of(EPOCH_SECRETS_LABEL, group_id)?)
+    }
+
+    // ---- crypto object readers ----
+
+    fn signature_key_pair<
+        SignaturePublicKey: traits::SignaturePublicKey<CURRENT_VERSION>,
+        SignatureKeyPair: traits::SignatureKeyPair<CURRENT_VERSION>,
+    >(
+        &self,
+        public_key: &SignaturePublicKey,
+    ) -> Result<Option<SignatureKeyPair>, Self::Error> {
+        self.read(key_of(SIGNATURE_KEY_PAIR_LABEL, public_key)?)
+    }
+
+    fn encryption_key_pair<
+        HpkeKeyPair: traits::HpkeKeyPair<CURRENT_VERSION>,
+        EncryptionKey: traits::EncryptionKey<CURRENT_VERSION>,
+    >(
+        &self,
+        public_key: &EncryptionKey,
+    ) -> Result<Option<HpkeKeyPair>, Self::Error> {
+        self.read(key_of(ENCRYPTION_KEY_PAIR_LABEL, public_key)?)
+    }
+
+    fn encryption_epoch_key_pairs<
+        GroupId: traits::GroupId<CURRENT_VERSION>,
+        EpochKey: traits::EpochKey<CURRENT_VERSION>,
+        HpkeKeyPair: traits::HpkeKeyPair<CURRENT_VERSION>,
+    >(
+        &self,
+        group_id: &GroupId,
+        epoch: &EpochKey,
+        leaf_index: u32,
+    ) -> Result<Vec<HpkeKeyPair>, Self::Error> {
+        let key = storage_key(
+            EPOCH_KEY_PAIRS_LABEL,
+            &epoch_key_pairs_id(group_id, epoch, leaf_index)?,
+        );
+        Ok(self.read(key)?.unwrap_or_default())
+    }
+
+    fn key_package<
+        KeyPackageRef: traits::HashReference<CURRENT_VERSION>,
+        KeyPackage: traits::KeyPackage<CURRENT_VERSION>,
+    >(
+        &self,
+        hash_ref: &KeyPackageRef,
+    ) -> Result<Option<KeyPackage>, Self::Error> {
+        self.read(key_of(KEY_PACKAGE_LABEL, hash_ref)?)
+    }
+
+    fn psk<PskBundle: traits::PskBundle<CURRENT_VERSION>, PskId: traits::PskId<CURRENT_VERSION>>(
+        &self,
+        psk_id: &PskId,
+    ) -> Result<Option<PskBundle>, Self::Error> {
+        self.read(key_of(PSK_LABEL, psk_id)?)
+    }
+
+    // ---- deleters ----
+
+    fn remove_proposal<
+        GroupId: traits::GroupId<CURRENT_VERSION>,
+        ProposalRef: traits::ProposalRef<CURRENT_VERSION>,
+    >(
+        &self,
+        group_id: &GroupId,
+        proposal_ref: &ProposalRef,
+    ) -> Result<(), Self::Error> {
+        self.remove_item(key_of(PROPOSAL_QUEUE_REFS_LABEL, group_id)?, proposal_ref)?;
+        self.delete(key_of(QUEUED_PROPOSAL_LABEL, &(group_id, proposal_ref))?)
+    }
+
+    fn delete_own_leaf_nodes<GroupId: traits::GroupId<CURRENT_VERSION>>(
+        &self,
+        group_id: &GroupId,
+    ) -> Result<(), Self::Error> {
+        self.delete(key_of(OWN_LEAF_NODES_LABEL, group_id)?)
+    }
+
+    fn delete_group_config<GroupId: traits::GroupId<CURRENT_VERSION>>(
+        &self,
+        group_id: &GroupId,
+    ) -> Result<(), Self::Error> {
+        self.delete(key_of(JOIN_CONFIG_LABEL, group_id)?)
+    }
+
+    fn delete_tree<GroupId: traits::GroupId<CURRENT_VERSION>>(
+        &self,
+        group_id: &GroupId,
+    ) -> Result<(), Self::Error> {
+        self.delete(key_of(TREE_LABEL, group_id)?)
+    }
+
+    fn delete_confirmation_tag<GroupId: traits::GroupId<CURRENT_VERSION>>(
+        &self,
+        group_id: &GroupId,
+    ) -> Result<(), Self::Error> {
+        self.delete(key_of(CONFIRMATION_TAG_LABEL, group_id)?)
+    }
+
+    fn delete_group_state<GroupId: traits::GroupId<CURRENT_VERSION>>(
+        &self,
+        group_id: &GroupId,
+    ) -> Result<(), Self::Error> {
+        self.delete(key_of(GROUP_STATE_LABEL, group_id)?)
+    }
+
+    fn delete_context<GroupId: traits::GroupId<CURRENT_VERSION>>(
+        &self,
+        group_id: &GroupId,
+    ) -> Result<(), Self::Error> {
+        self.delete(key_of(GROUP_CONTEXT_LABEL, group_id)?)
+    }
+
+    fn delete_interim_transcript_hash<GroupId: traits::GroupId<CURRENT_VERSION>>(
+        &self,
+        group_id: &GroupId,
+    ) -> Result<(), Self::Error> {
+        self.delete(key_of(INTERIM_TRANSCRIPT_HASH_LABEL, group_id)?)
+    }
+
+    fn delete_message_secrets<GroupId: traits::GroupId<CURRENT_VERSION>>(
+        &self,
+        group_id: &GroupId,
+    ) -> Result<(), Self::Error> {
+        self.delete(key_of(MESSAGE_SECRETS_LABEL, group_id)?)
+    }
+
+    fn delete_all_resumption_psk_secrets<GroupId: traits::GroupId<CURRENT_VERSION>>(
+        &self,
+        group_id: &GroupId,
+    ) -> Result<(), Self::Error> {
+        self.delete(key_of(RESUMPTION_PSK_STORE_LABEL, group_id)?)
+    }
+
+    fn delete_own_leaf_index<GroupId: traits::GroupId<CURRENT_VERSION>>(
+        &self,
+        group_id: &GroupId,
+    ) -> Result<(), Self::Error> {
+        self.delete(key_of(OWN_LEAF_NODE_INDEX_LABEL, group_id)?)
+    }
+
+    fn delete_group_epoch_secrets<GroupId: traits::GroupId<CURRENT_VERSION>>(
+        &self,
+        group_id: &GroupId,
+    ) -> Result<(), Self::Error> {
+        self.delete(key_of(EPOCH_SECRETS_LABEL, group_id)?)
+    }
+
+    fn clear_proposal_queue<
+        GroupId: traits::GroupId<CURRENT_VERSION>,
+        ProposalRef: traits::ProposalRef<CURRENT_VERSION>,
+    >(
+        &self,
+        group_id: &GroupId,
+    ) -> Result<(), Self::Error> {
+        let refs: Vec<ProposalRef> =
+            self.read_list(key_of(PROPOSAL_QUEUE_REFS_LABEL, group_id)?)?;
+        for proposal_ref in refs {
+            self.delete(key_of(QUEUED_PROPOSAL_LABEL, &(group_id, proposal_ref))?)?;
+        }
+        self.delete(key_of(PROPOSAL_QUEUE_REFS_LABEL, group_id)?)
+    }
+
+    fn delete_signature_key_pair<
+        SignaturePublicKey: traits::SignaturePublicKey<CURRENT_VERSION>,
+    >(
+        &self,
+        public_key: &SignaturePublicKey,
+    ) -> Result<(), Self::Error> {
+        self.delete(key_of(SIGNATURE_KEY_PAIR_LABEL, public_key)?)
+    }
+
+    fn delete_encryption_key_pair<EncryptionKey: traits::EncryptionKey<CURRENT_VERSION>>(
+        &self,
+        public_key: &EncryptionKey,
+    ) -> Result<(), Self::Error> {
+        self.delete(key_of(ENCRYPTION_KEY_PAIR_LABEL, public_key)?)
+    }
+
+    fn delete_encryption_epoch_key_pairs<
+        GroupId: traits::GroupId<CURRENT_VERSION>,
+        EpochKey: traits::EpochKey<CURRENT_VERSION>,
+    >(
+        &self,
+        group_id: &GroupId,
+        epoch: &EpochKey,
+        leaf_index: u32,
+    ) -> Result<(), Self::Error> {
+        let key = storage_key(
+            EPOCH_KEY_PAIRS_LABEL,
+            &epoch_key_pairs_id(group_id, epoch, leaf_index)?,
+        );
+        self.delete(key)
+    }
+
+    fn delete_key_package<KeyPackageRef: traits::HashReference<CURRENT_VERSION>>(
+        &self,
+        hash_ref: &KeyPackageRef,
+    ) -> Result<(), Self::Error> {
+        self.delete(key_of(KEY_PACKAGE_LABEL, hash_ref)?)
+    }
+
+    fn delete_psk<PskKey: traits::PskId<CURRENT_VERSION>>(
+        &self,
+        psk_id: &PskKey,
+    ) -> Result<(), Self::Error> {
+        self.delete(key_of(PSK_LABEL, psk_id)?)
+    }
+}
+
+/// openmls provider for a session: RustCrypto primitives over `SessionStorage`.
+pub struct SessionProvider {
+    crypto: RustCrypto,
+    storage: SessionStorage,
+}
+
+impl SessionProvider {
+    pub fn new(storage: SessionStorage) -> Self {
+        SessionProvider {
+            crypto: RustCrypto::default(),
+            storage,
+        }
+    }
+}
+
+impl OpenMlsProvider for SessionProvider {
+    type CryptoProvider = RustCrypto;
+    type RandProvider = RustCrypto;
+    type StorageProvider = SessionStorage;
+
+    fn storage(&self) -> &Self::StorageProvider {
+        &self.storage
+    }
+
+    fn crypto(&self) -> &Self::CryptoProvider {
+        &self.crypto
+    }
+
+    fn rand(&self) -> &Self::RandProvider {
+        &self.crypto
+    }
+}
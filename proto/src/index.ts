@@ -74,6 +74,8 @@ export type {
   MlsAddMemberResult,
   MlsProcessedMessage,
   MlsGroupMember,
+  MlsMemberDevice,
+  MlsDeviceInfo,
   KeyBackupPayload,
 } from "./mls/index.js";
 
@@ -2,7 +2,9 @@ import { describe, it, expect, beforeAll, afterEach } from "vitest";
 import fs from "fs";
 import path from "path";
 import { MlsClient } from "../client.js";
+import { isMlsError } from "../errors.js";
 import { ensureSodium, generateIdentityKeyPair } from "../../crypto/index.js";
+import type { MlsCredential } from "../types.js";
 
 // Each test uses a fresh MlsClient with its own WASM instance would be ideal,
 // but WASM module is shared. We create separate MlsClient instances per "user"
@@ -73,6 +75,7 @@ describe("MLS add member", () => {
       groupId,
       bobPackages[0].keyPackageData,
     );
+    alice.confirmPendingCommit(groupId);
     expect(commit).toBeInstanceOf(Uint8Array);
     expect(commit.length).toBeGreaterThan(0);
     expect(welcome).toBeInstanceOf(Uint8Array);
@@ -99,10 +102,81 @@ describe("MLS add member", () => {
 
     const bobPackages = bob.generateSessionKeyPackages(1);
     alice.addMember(groupId, bobPackages[0].keyPackageData);
+    // Nothing changes until the server has accepted the commit
+    expect(alice.getEpoch(groupId)).toBe(0);
+    alice.confirmPendingCommit(groupId);
 
     // Epoch advances after add+merge
     expect(alice.getEpoch(groupId)).toBe(1);
   });
+
+  it("adds several members in one commit and reports rejected packages", async () => {
+    const alice = await setupClient();
+    const bob = await setupClient();
+    const carol = await setupClient();
+    const groupId = makeGroupId();
+
+    alice.createGroup(groupId);
+    const bobPkg = bob.generateSessionKeyPackages(1)[0].keyPackageData;
+    const carolPkg = carol.generateSessionKeyPackages(1)[0].keyPackageData;
+
+    const { welcome, rejected } = alice.addMembers(groupId, [
+      bobPkg,
+      new Uint8Array([1, 2, 3]),
+      carolPkg,
+      bobPkg,
+    ]);
+    alice.confirmPendingCommit(groupId);
+    expect(rejected.map((r) => r.index)).toEqual([1, 3]);
+    expect(alice.getEpoch(groupId)).toBe(1);
+
+    bob.processWelcome(welcome!);
+    carol.processWelcome(welcome!);
+    expect(carol.getMembers(groupId)).toHaveLength(3);
+  });
+
+  it("makes no commit when every package is rejected", async () => {
+    const alice = await setupClient();
+    const groupId = makeGroupId();
+
+    alice.createGroup(groupId);
+    const result = alice.addMembers(groupId, [new Uint8Array([1, 2, 3])]);
+    expect(result.commit).toBeUndefined();
+    expect(result.rejected).toHaveLength(1);
+    expect(alice.getEpoch(groupId)).toBe(0);
+  });
+});
+
+describe("MLS ciphersuites", () => {
+  it.each([
+    ["X25519/ChaCha20-Poly1305/Ed25519", 3],
+    ["P-256/AES-128-GCM/P-256", 2],
+  ])("creates and uses a %s group", async (_name, ciphersuite) => {
+    const makeClient = async () => {
+      const client = new MlsClient();
+      client.init(wasmBytes);
+      const identity = await generateIdentityKeyPair();
+      client.createSession(client.createCredential(identity.publicKey, ciphersuite));
+      return client;
+    };
+    const alice = await makeClient();
+    const bob = await makeClient();
+    const groupId = makeGroupId();
+
+    alice.createGroup(groupId);
+    const bobPkgs = bob.generateSessionKeyPackages(1);
+    const { welcome } = alice.addMember(groupId, bobPkgs[0].keyPackageData);
+    alice.confirmPendingCommit(groupId);
+    bob.processWelcome(welcome);
+
+    const ct = alice.encryptMessage(groupId, new TextEncoder().encode("suite"));
+    expect(new TextDecoder().decode(bob.processMessage(groupId, ct).plaintext)).toBe("suite");
+  });
+
+  it("rejects a group ciphersuite that doesn't match the signing key", async () => {
+    const alice = await setupClient();
+    expect(() => alice.createGroup(makeGroupId(), 2)).toThrow("does not match");
+  });
 });
 
 describe("MLS encrypt/decrypt", () => {
@@ -115,6 +189,7 @@ describe("MLS encrypt/decrypt", () => {
     alice.createGroup(groupId);
     const bobPackages = bob.generateSessionKeyPackages(1);
     const { welcome } = alice.addMember(groupId, bobPackages[0].keyPackageData);
+    alice.confirmPendingCommit(groupId);
     bob.processWelcome(welcome);
 
     // Alice encrypts a message
@@ -146,6 +221,7 @@ describe("MLS encrypt/decrypt", () => {
     alice.createGroup(groupId);
     const bobPkgs = bob.generateSessionKeyPackages(1);
     const { welcome } = alice.addMember(groupId, bobPkgs[0].keyPackageData);
+    alice.confirmPendingCommit(groupId);
     bob.processWelcome(welcome);
 
     const plaintext = new TextEncoder().encode("same message");
@@ -163,6 +239,37 @@ describe("MLS encrypt/decrypt", () => {
   });
 });
 
+describe("MLS authenticated data", () => {
+  it("delivers AAD alongside the plaintext", async () => {
+    const alice = await setupClient();
+    const bob = await setupClient();
+    const groupId = makeGroupId();
+
+    alice.createGroup(groupId);
+    const bobPkgs = bob.generateSessionKeyPackages(1);
+    const { welcome } = alice.addMember(groupId, bobPkgs[0].keyPackageData);
+    alice.confirmPendingCommit(groupId);
+    bob.processWelcome(welcome);
+
+    const aad = new TextEncoder().encode("msg-id:42");
+    const ct = alice.encryptMessage(
+      groupId,
+      new TextEncoder().encode("hello"),
+      aad,
+    );
+    const result = bob.processMessage(groupId, ct);
+    expect(new TextDecoder().decode(result.plaintext)).toBe("hello");
+    expect(result.authenticatedData).toEqual(aad);
+
+    // AAD applies to a single message only
+    const next = bob.processMessage(
+      groupId,
+      alice.encryptMessage(groupId, new TextEncoder().encode("again")),
+    );
+    expect(next.authenticatedData.length).toBe(0);
+  });
+});
+
 describe("MLS three-member group", () => {
   it("three members can all communicate", async () => {
     const alice = await setupClient();
@@ -177,6 +284,7 @@ describe("MLS three-member group", () => {
       groupId,
       bobPkgs[0].keyPackageData,
     );
+    alice.confirmPendingCommit(groupId);
     bob.processWelcome(bobWelcome);
 
     // Alice adds Carol
@@ -185,6 +293,7 @@ describe("MLS three-member group", () => {
       groupId,
       carolPkgs[0].keyPackageData,
     );
+    alice.confirmPendingCommit(groupId);
 
     // Bob processes the add-Carol commit
     bob.processMessage(groupId, addCarolCommit);
@@ -247,6 +356,7 @@ describe("MLS remove member", () => {
       groupId,
       bobPkgs[0].keyPackageData,
     );
+    alice.confirmPendingCommit(groupId);
     bob.processWelcome(bobWelcome);
 
     const carolPkgs = carol.generateSessionKeyPackages(1);
@@ -254,23 +364,19 @@ describe("MLS remove member", () => {
       groupId,
       carolPkgs[0].keyPackageData,
     );
+    alice.confirmPendingCommit(groupId);
     bob.processMessage(groupId, addCarolCommit);
     carol.processWelcome(carolWelcome);
 
     // Find Carol's leaf index
     const members = alice.getMembers(groupId);
-    const carolMember = members.find(
-      (m) => m.index !== alice.getMembers(groupId)[0].index &&
-             m.index !== members.find((x) => x.index !== members[0].index)?.index,
-    );
-    // Carol should be at index 2 (alice=0, bob=1, carol=2)
-    // But leaf indices in MLS may not be sequential. Let's find the right one.
     expect(members).toHaveLength(3);
 
     // Alice removes Carol (last member added)
     // In the binary tree, leaf indices are 0, 1, 2 for Alice, Bob, Carol
-    const carolLeafIndex = members[2].index;
-    const removeCommit = alice.removeMember(groupId, carolLeafIndex);
+    const carolLeafIndex = members[2].devices[0].index;
+    const { commit: removeCommit } = alice.removeMember(groupId, carolLeafIndex);
+    alice.confirmPendingCommit(groupId);
 
     // Bob processes the remove commit
     bob.processMessage(groupId, removeCommit);
@@ -299,16 +405,120 @@ describe("MLS remove member", () => {
     alice.createGroup(groupId);
     const bobPkgs = bob.generateSessionKeyPackages(1);
     const { welcome } = alice.addMember(groupId, bobPkgs[0].keyPackageData);
+    alice.confirmPendingCommit(groupId);
     bob.processWelcome(welcome);
 
     const epochBeforeRemove = alice.getEpoch(groupId);
     const members = alice.getMembers(groupId);
-    const bobLeafIndex = members[1].index;
+    const bobLeafIndex = members[1].devices[0].index;
 
     alice.removeMember(groupId, bobLeafIndex);
+    alice.confirmPendingCommit(groupId);
 
     expect(alice.getEpoch(groupId)).toBe(epochBeforeRemove + 1);
   });
+
+  it("rejects removing an empty leaf or our own", async () => {
+    const alice = await setupClient();
+    const bob = await setupClient();
+    const groupId = makeGroupId();
+
+    alice.createGroup(groupId);
+    const [bobPkg] = bob.generateSessionKeyPackages(1);
+    alice.addMember(groupId, bobPkg.keyPackageData);
+    alice.confirmPendingCommit(groupId);
+    alice.removeMember(groupId, 1);
+    alice.confirmPendingCommit(groupId);
+
+    const codeOf = (leafIndex: number) => {
+      try {
+        alice.removeMember(groupId, leafIndex);
+      } catch (err) {
+        return isMlsError(err) && err.code;
+      }
+      return undefined;
+    };
+    expect(codeOf(1)).toBe("member_not_found");
+    expect(codeOf(7)).toBe("member_not_found");
+    expect(codeOf(0)).toBe("invalid_input");
+    expect(alice.hasPendingCommit(groupId)).toBe(false);
+  });
+});
+
+describe("MLS commit details", () => {
+  it("reports added members, the committer's key update and the new epoch", async () => {
+    const alice = await setupClient();
+    const bob = await setupClient();
+    const carol = await setupClient();
+    const groupId = makeGroupId();
+
+    alice.createGroup(groupId);
+    const bobPkgs = bob.generateSessionKeyPackages(1);
+    bob.processWelcome(alice.addMember(groupId, bobPkgs[0].keyPackageData).welcome);
+    alice.confirmPendingCommit(groupId);
+
+    const carolPkgs = carol.generateSessionKeyPackages(1);
+    const { commit } = alice.addMember(groupId, carolPkgs[0].keyPackageData);
+    alice.confirmPendingCommit(groupId);
+    const processed = bob.processMessage(groupId, commit);
+
+    const members = alice.getMembers(groupId);
+    expect(processed.commit).toBeDefined();
+    expect(processed.commit!.epoch).toBe(2);
+    expect(processed.commit!.added).toEqual([
+      { index: members[2].devices[0].index, identity: members[2].identity },
+    ]);
+    expect(processed.commit!.removed).toEqual([]);
+    expect(processed.commit!.updated.map((m) => m.index)).toEqual([
+      members[0].devices[0].index,
+    ]);
+    expect(processed.commit!.selfRemoved).toBe(false);
+  });
+
+  it("reports removed members and tells the removed member", async () => {
+    const alice = await setupClient();
+    const bob = await setupClient();
+    const carol = await setupClient();
+    const groupId = makeGroupId();
+
+    alice.createGroup(groupId);
+    const bobPkgs = bob.generateSessionKeyPackages(1);
+    const carolPkgs = carol.generateSessionKeyPackages(1);
+    const { welcome } = alice.addMembers(groupId, [
+      bobPkgs[0].keyPackageData,
+      carolPkgs[0].keyPackageData,
+    ]);
+    alice.confirmPendingCommit(groupId);
+    bob.processWelcome(welcome!);
+    carol.processWelcome(welcome!);
+
+    const carolMember = alice.getMembers(groupId)[2];
+    const carolIndex = carolMember.devices[0].index;
+    const { commit } = alice.removeMember(groupId, carolIndex);
+    alice.confirmPendingCommit(groupId);
+
+    const seenByBob = bob.processMessage(groupId, commit).commit!;
+    expect(seenByBob.removed).toEqual([
+      { index: carolIndex, identity: carolMember.identity },
+    ]);
+    expect(seenByBob.selfRemoved).toBe(false);
+
+    expect(carol.processMessage(groupId, commit).commit!.selfRemoved).toBe(true);
+  });
+
+  it("is absent for application messages", async () => {
+    const alice = await setupClient();
+    const bob = await setupClient();
+    const groupId = makeGroupId();
+
+    alice.createGroup(groupId);
+    const bobPkgs = bob.generateSessionKeyPackages(1);
+    bob.processWelcome(alice.addMember(groupId, bobPkgs[0].keyPackageData).welcome);
+    alice.confirmPendingCommit(groupId);
+
+    const ct = alice.encryptMessage(groupId, new TextEncoder().encode("hi"));
+    expect(bob.processMessage(groupId, ct).commit).toBeUndefined();
+  });
 });
 
 describe("MLS epoch tracking", () => {
@@ -322,11 +532,321 @@ describe("MLS epoch tracking", () => {
 
     const bobPkgs = bob.generateSessionKeyPackages(1);
     alice.addMember(groupId, bobPkgs[0].keyPackageData);
+    alice.confirmPendingCommit(groupId);
     expect(alice.getEpoch(groupId)).toBe(1);
 
     const members = alice.getMembers(groupId);
-    alice.removeMember(groupId, members[1].index);
+    alice.removeMember(groupId, members[1].devices[0].index);
+    alice.confirmPendingCommit(groupId);
+    expect(alice.getEpoch(groupId)).toBe(2);
+  });
+});
+
+describe("MLS self update", () => {
+  it("rotates our leaf keys and other members follow", async () => {
+    const alice = await setupClient();
+    const bob = await setupClient();
+    const groupId = makeGroupId();
+
+    alice.createGroup(groupId);
+    const bobPkgs = bob.generateSessionKeyPackages(1);
+    const { welcome } = alice.addMember(groupId, bobPkgs[0].keyPackageData);
+    alice.confirmPendingCommit(groupId);
+    bob.processWelcome(welcome);
+
+    const { commit } = bob.selfUpdate(groupId);
+    bob.confirmPendingCommit(groupId);
+    expect(bob.getEpoch(groupId)).toBe(2);
+    expect(bob.getEpochsSinceSelfUpdate(groupId)).toBe(0);
+
+    const processed = alice.processMessage(groupId, commit);
+    expect(processed.messageType).toBe("commit");
     expect(alice.getEpoch(groupId)).toBe(2);
+    expect(alice.getEpochsSinceSelfUpdate(groupId)).toBe(1);
+
+    const ct = bob.encryptMessage(groupId, new TextEncoder().encode("fresh keys"));
+    expect(new TextDecoder().decode(alice.processMessage(groupId, ct).plaintext)).toBe(
+      "fresh keys",
+    );
+  });
+
+  it("counts another member committing our update proposal", async () => {
+    const alice = await setupClient();
+    const bob = await setupClient();
+    const groupId = makeGroupId();
+
+    alice.createGroup(groupId);
+    const bobPkgs = bob.generateSessionKeyPackages(1);
+    bob.processWelcome(alice.addMember(groupId, bobPkgs[0].keyPackageData).welcome);
+    alice.confirmPendingCommit(groupId);
+    bob.processMessage(groupId, alice.selfUpdate(groupId).commit);
+    alice.confirmPendingCommit(groupId);
+    expect(bob.getEpochsSinceSelfUpdate(groupId)).toBe(1);
+
+    const { proposal } = bob.proposeSelfUpdate(groupId);
+    alice.processMessage(groupId, proposal);
+    const { commit } = alice.commitPendingProposals(groupId);
+    alice.confirmPendingCommit(groupId);
+    bob.processMessage(groupId, commit);
+    expect(bob.getEpochsSinceSelfUpdate(groupId)).toBe(0);
+  });
+});
+
+describe("MLS proposals", () => {
+  it("a member proposes an add and another member commits it", async () => {
+    const alice = await setupClient();
+    const bob = await setupClient();
+    const carol = await setupClient();
+    const groupId = makeGroupId();
+
+    alice.createGroup(groupId);
+    const bobPkgs = bob.generateSessionKeyPackages(1);
+    bob.processWelcome(alice.addMember(groupId, bobPkgs[0].keyPackageData).welcome);
+    alice.confirmPendingCommit(groupId);
+
+    const carolPkgs = carol.generateSessionKeyPackages(1);
+    const { proposal, proposalRef } = bob.proposeAdd(groupId, carolPkgs[0].keyPackageData);
+    expect(alice.processMessage(groupId, proposal).messageType).toBe("proposal");
+
+    const pending = alice.getPendingProposals(groupId);
+    expect(pending).toHaveLength(1);
+    expect(pending[0].proposalType).toBe("Add");
+    expect(pending[0].senderIndex).toBe(1);
+    expect(pending[0].proposalRef).toEqual(proposalRef);
+
+    const { commit, welcome } = alice.commitPendingProposals(groupId);
+    alice.confirmPendingCommit(groupId);
+    expect(welcome).toBeDefined();
+    expect(alice.getPendingProposals(groupId)).toHaveLength(0);
+
+    bob.processMessage(groupId, commit);
+    carol.processWelcome(welcome!);
+    expect(carol.getEpoch(groupId)).toBe(2);
+    expect(bob.getMembers(groupId)).toHaveLength(3);
+  });
+
+  it("commits queued remove and update proposals without a Welcome", async () => {
+    const alice = await setupClient();
+    const bob = await setupClient();
+    const carol = await setupClient();
+    const groupId = makeGroupId();
+
+    alice.createGroup(groupId);
+    const bobPkgs = bob.generateSessionKeyPackages(1);
+    const carolPkgs = carol.generateSessionKeyPackages(1);
+    bob.processWelcome(alice.addMember(groupId, bobPkgs[0].keyPackageData).welcome);
+    alice.confirmPendingCommit(groupId);
+    const add = alice.addMember(groupId, carolPkgs[0].keyPackageData);
+    alice.confirmPendingCommit(groupId);
+    bob.processMessage(groupId, add.commit);
+    carol.processWelcome(add.welcome);
+
+    const update = bob.proposeSelfUpdate(groupId);
+    alice.processMessage(groupId, update.proposal);
+    const remove = alice.proposeRemove(groupId, 2);
+
+    const { commit, welcome } = alice.commitPendingProposals(groupId);
+    alice.confirmPendingCommit(groupId);
+    expect(welcome).toBeUndefined();
+
+    bob.processMessage(groupId, remove.proposal);
+    bob.processMessage(groupId, commit);
+    expect(bob.getMembers(groupId)).toHaveLength(2);
+
+    const ct = bob.encryptMessage(groupId, new TextEncoder().encode("two of us"));
+    expect(new TextDecoder().decode(alice.processMessage(groupId, ct).plaintext)).toBe(
+      "two of us",
+    );
+  });
+
+  it("blocks sending while a proposal is queued", async () => {
+    const alice = await setupClient();
+    const bob = await setupClient();
+    const groupId = makeGroupId();
+
+    alice.createGroup(groupId);
+    const bobPkgs = bob.generateSessionKeyPackages(1);
+    bob.processWelcome(alice.addMember(groupId, bobPkgs[0].keyPackageData).welcome);
+    alice.confirmPendingCommit(groupId);
+
+    const { proposal } = bob.proposeSelfUpdate(groupId);
+    alice.processMessage(groupId, proposal);
+    const text = new TextEncoder().encode("anyone there?");
+    for (const member of [bob, alice]) {
+      let error: unknown;
+      try {
+        member.encryptMessage(groupId, text);
+      } catch (err) {
+        error = err;
+      }
+      expect(isMlsError(error) && error.code).toBe("pending_proposal");
+    }
+
+    const { commit } = alice.commitPendingProposals(groupId);
+    alice.confirmPendingCommit(groupId);
+    bob.processMessage(groupId, commit);
+    const ct = bob.encryptMessage(groupId, text);
+    expect(new TextDecoder().decode(alice.processMessage(groupId, ct).plaintext)).toBe(
+      "anyone there?",
+    );
+  });
+});
+
+describe("MLS group info", () => {
+  it("every commit returns a GroupInfo for the new epoch", async () => {
+    const alice = await setupClient();
+    const bob = await setupClient();
+    const groupId = makeGroupId();
+
+    alice.createGroup(groupId);
+    const bobPkgs = bob.generateSessionKeyPackages(1);
+    const added = alice.addMember(groupId, bobPkgs[0].keyPackageData);
+    alice.confirmPendingCommit(groupId);
+    expect(added.groupInfo.length).toBeGreaterThan(0);
+    bob.processWelcome(added.welcome);
+
+    const update = bob.selfUpdate(groupId);
+    bob.confirmPendingCommit(groupId);
+    expect(update.groupInfo.length).toBeGreaterThan(0);
+    alice.processMessage(groupId, update.commit);
+
+    const removed = alice.removeMember(groupId, 1);
+    alice.confirmPendingCommit(groupId);
+    expect(removed.groupInfo.length).toBeGreaterThan(0);
+    expect(removed.groupInfo).not.toEqual(added.groupInfo);
+  });
+
+  it("exports GroupInfo with or without the ratchet tree", async () => {
+    const alice = await setupClient();
+    const groupId = makeGroupId();
+
+    alice.createGroup(groupId);
+    const withTree = alice.exportGroupInfo(groupId, true);
+    const withoutTree = alice.exportGroupInfo(groupId, false);
+    expect(withTree.length).toBeGreaterThan(withoutTree.length);
+  });
+});
+
+describe("MLS external commit join", () => {
+  it("joins from a stored GroupInfo without a Welcome", async () => {
+    const alice = await setupClient();
+    const bob = await setupClient();
+    const groupId = makeGroupId();
+
+    alice.createGroup(groupId);
+    const groupInfo = alice.exportGroupInfo(groupId);
+
+    const joined = bob.joinByExternalCommit(groupInfo);
+    expect(joined.groupId).toEqual(groupId);
+    expect(alice.processMessage(groupId, joined.commit).messageType).toBe("commit");
+    expect(alice.getMembers(groupId)).toHaveLength(2);
+
+    const ct = bob.encryptMessage(groupId, new TextEncoder().encode("let me in"));
+    expect(new TextDecoder().decode(alice.processMessage(groupId, ct).plaintext)).toBe(
+      "let me in",
+    );
+  });
+
+  it("recovers a member that fell out of sync", async () => {
+    const alice = await setupClient();
+    const bob = await setupClient();
+    const groupId = makeGroupId();
+
+    alice.createGroup(groupId);
+    const bobPkgs = bob.generateSessionKeyPackages(1);
+    bob.processWelcome(alice.addMember(groupId, bobPkgs[0].keyPackageData).welcome);
+    alice.confirmPendingCommit(groupId);
+
+    // Bob misses this commit
+    const { groupInfo } = alice.selfUpdate(groupId);
+    alice.confirmPendingCommit(groupId);
+
+    const { commit } = bob.joinByExternalCommit(groupInfo);
+    alice.processMessage(groupId, commit);
+
+    // Bob's stale leaf was replaced, not duplicated
+    expect(alice.getMembers(groupId)).toHaveLength(2);
+    expect(bob.getEpoch(groupId)).toBe(alice.getEpoch(groupId));
+  });
+
+  it("accepts the ratchet tree separately", async () => {
+    const alice = await setupClient();
+    const bob = await setupClient();
+    const groupId = makeGroupId();
+
+    alice.createGroup(groupId);
+    const groupInfo = alice.exportGroupInfo(groupId, false);
+    expect(() => bob.joinByExternalCommit(groupInfo)).toThrow();
+
+    const tree = alice.exportRatchetTree(groupId);
+    const { commit } = bob.joinByExternalCommit(groupInfo, tree);
+    alice.processMessage(groupId, commit);
+    expect(alice.getMembers(groupId)).toHaveLength(2);
+  });
+
+  it("keeps the local group when a rejoin fails", async () => {
+    const alice = await setupClient();
+    const bob = await setupClient();
+    const groupId = makeGroupId();
+
+    alice.createGroup(groupId);
+    const bobPkgs = bob.generateSessionKeyPackages(1);
+    bob.processWelcome(alice.addMember(groupId, bobPkgs[0].keyPackageData).welcome);
+    alice.confirmPendingCommit(groupId);
+
+    // No ratchet tree anywhere: the join can't be built
+    const groupInfo = alice.exportGroupInfo(groupId, false);
+    expect(() => bob.joinByExternalCommit(groupInfo)).toThrow();
+    expect(bob.getEpoch(groupId)).toBe(1);
+
+    const ct = alice.encryptMessage(groupId, new TextEncoder().encode("still in"));
+    expect(new TextDecoder().decode(bob.processMessage(groupId, ct).plaintext)).toBe(
+      "still in",
+    );
+  });
+});
+
+describe("MLS exporter secrets", () => {
+  it("members derive the same secret and it changes each epoch", async () => {
+    const alice = await setupClient();
+    const bob = await setupClient();
+    const groupId = makeGroupId();
+
+    alice.createGroup(groupId);
+    const bobPkgs = bob.generateSessionKeyPackages(1);
+    const { welcome } = alice.addMember(groupId, bobPkgs[0].keyPackageData);
+    alice.confirmPendingCommit(groupId);
+    bob.processWelcome(welcome);
+
+    const context = new TextEncoder().encode("room-1");
+    const aliceKey = alice.exportSecret(groupId, "cairn-voice", context, 16);
+    const bobKey = bob.exportSecret(groupId, "cairn-voice", context, 16);
+    expect(aliceKey).toHaveLength(16);
+    expect(aliceKey).toEqual(bobKey);
+
+    // Different labels yield independent keys
+    expect(alice.exportSecret(groupId, "cairn-file", context, 16)).not.toEqual(aliceKey);
+
+    // A new epoch yields a new key
+    const carol = await setupClient();
+    const carolPkgs = carol.generateSessionKeyPackages(1);
+    alice.addMember(groupId, carolPkgs[0].keyPackageData);
+    alice.confirmPendingCommit(groupId);
+    expect(alice.exportSecret(groupId, "cairn-voice", context, 16)).not.toEqual(aliceKey);
+  });
+
+  it("rejects an empty secret", async () => {
+    const alice = await setupClient();
+    const groupId = makeGroupId();
+    alice.createGroup(groupId);
+
+    let error: unknown;
+    try {
+      alice.exportSecret(groupId, "cairn-voice", new Uint8Array(), 0);
+    } catch (err) {
+      error = err;
+    }
+    expect(isMlsError(error) && error.code).toBe("invalid_input");
   });
 });
 
@@ -361,4 +881,1157 @@ describe("MLS session lifecycle", () => {
     // Old group should not be found in new session
     expect(() => client.getEpoch(groupId)).toThrow("group not found");
   });
+
+  it("exportSession/importSession restores groups", async () => {
+    const alice = await setupClient();
+    const bob = await setupClient();
+    const groupId = makeGroupId();
+
+    alice.createGroup(groupId);
+    const bobPkgs = bob.generateSessionKeyPackages(1);
+    const { welcome } = alice.addMember(groupId, bobPkgs[0].keyPackageData);
+    alice.confirmPendingCommit(groupId);
+    bob.processWelcome(welcome);
+
+    const key = new Uint8Array(32);
+    crypto.getRandomValues(key);
+    const blob = bob.exportSession(key);
+
+    // Restore into a fresh client, as after a page reload
+    const restored = new MlsClient();
+    restored.init(wasmBytes);
+    restored.importSession(blob, key);
+    expect(restored.getEpoch(groupId)).toBe(bob.getEpoch(groupId));
+
+    const ct = alice.encryptMessage(groupId, new TextEncoder().encode("after reload"));
+    const result = restored.processMessage(groupId, ct);
+    expect(new TextDecoder().decode(result.plaintext)).toBe("after reload");
+  });
+
+  it("importSession rejects the wrong key", async () => {
+    const client = await setupClient();
+    client.createGroup(makeGroupId());
+
+    const blob = client.exportSession(new Uint8Array(32).fill(1));
+    const other = new MlsClient();
+    other.init(wasmBytes);
+    expect(() => other.importSession(blob, new Uint8Array(32).fill(2))).toThrow(
+      "wrong key",
+    );
+  });
+
+  it("session backed by external storage survives a restart", async () => {
+    const values = new Map<string, Uint8Array>();
+    const hex = (k: Uint8Array) => Buffer.from(k).toString("hex");
+    const storage = {
+      get: (k: Uint8Array) => values.get(hex(k)),
+      put: (k: Uint8Array, v: Uint8Array) => void values.set(hex(k), new Uint8Array(v)),
+      delete: (k: Uint8Array) => void values.delete(hex(k)),
+    };
+
+    const alice = await setupClient();
+    const bob = new MlsClient();
+    bob.init(wasmBytes);
+    const identity = await generateIdentityKeyPair();
+    const credential = bob.createCredential(identity.publicKey);
+    bob.createSession(credential, storage);
+
+    const groupId = makeGroupId();
+    alice.createGroup(groupId);
+    const bobPkgs = bob.generateSessionKeyPackages(1);
+    const { welcome } = alice.addMember(groupId, bobPkgs[0].keyPackageData);
+    alice.confirmPendingCommit(groupId);
+    bob.processWelcome(welcome);
+    expect(values.size).toBeGreaterThan(0);
+
+    // Reopen over the same storage, as after a crash
+    bob.createSession(credential, storage);
+    expect(bob.getEpoch(groupId)).toBe(1);
+
+    const ct = alice.encryptMessage(groupId, new TextEncoder().encode("still here"));
+    expect(new TextDecoder().decode(bob.processMessage(groupId, ct).plaintext)).toBe(
+      "still here",
+    );
+  });
+
+  it("hands external storage whole operations", async () => {
+    const committed = new Map<string, Uint8Array>();
+    const staged = new Map<string, Uint8Array | undefined>();
+    const hex = (k: Uint8Array) => Buffer.from(k).toString("hex");
+    let commits = 0;
+    const storage = {
+      get: (k: Uint8Array) => committed.get(hex(k)),
+      put: (k: Uint8Array, v: Uint8Array) => void staged.set(hex(k), new Uint8Array(v)),
+      delete: (k: Uint8Array) => void staged.set(hex(k), undefined),
+      commit: () => {
+        commits++;
+        for (const [k, v] of staged) {
+          if (v) committed.set(k, v);
+          else committed.delete(k);
+        }
+        staged.clear();
+      },
+    };
+
+    const bob = new MlsClient();
+    bob.init(wasmBytes);
+    const identity = await generateIdentityKeyPair();
+    bob.createSession(bob.createCredential(identity.publicKey), storage);
+    const groupId = makeGroupId();
+    bob.createGroup(groupId);
+    expect(staged.size).toBe(0);
+    const before = commits;
+
+    // A failed operation writes nothing
+    expect(() => bob.processWelcome(new Uint8Array([1, 2, 3]))).toThrow();
+    expect(() => bob.selfUpdate(makeGroupId())).toThrow();
+    expect(commits).toBe(before);
+    expect(staged.size).toBe(0);
+
+    bob.selfUpdate(groupId);
+    expect(commits).toBe(before + 1);
+    expect(bob.hasPendingCommit(groupId)).toBe(true);
+  });
+
+  it("stops a session whose storage failed partway through a commit", async () => {
+    const values = new Map<string, Uint8Array>();
+    const hex = (k: Uint8Array) => Buffer.from(k).toString("hex");
+    let failPuts = false;
+    const storage = {
+      get: (k: Uint8Array) => values.get(hex(k)),
+      put: (k: Uint8Array, v: Uint8Array) => {
+        if (failPuts) throw new Error("disk full");
+        values.set(hex(k), new Uint8Array(v));
+      },
+      delete: (k: Uint8Array) => void values.delete(hex(k)),
+    };
+
+    const bob = new MlsClient();
+    bob.init(wasmBytes);
+    const identity = await generateIdentityKeyPair();
+    const credential = bob.createCredential(identity.publicKey);
+    bob.createSession(credential, storage);
+    const groupId = makeGroupId();
+    bob.createGroup(groupId);
+
+    failPuts = true;
+    const codeOf = (fn: () => unknown) => {
+      try {
+        fn();
+      } catch (err) {
+        return isMlsError(err) && err.code;
+      }
+      return undefined;
+    };
+    expect(codeOf(() => bob.selfUpdate(groupId))).toBe("storage");
+    failPuts = false;
+    expect(codeOf(() => bob.getEpoch(groupId))).toBe("storage");
+
+    // Reopening over the store recovers
+    bob.createSession(credential, storage);
+    expect(bob.getEpoch(groupId)).toBe(0);
+  });
+});
+
+describe("MLS credential validation", () => {
+  const hex = (b: Uint8Array) => Buffer.from(b).toString("hex");
+
+  it("refuses to add a member the validator rejects", async () => {
+    const alice = await setupClient();
+    const mallory = await setupClient();
+    const groupId = makeGroupId();
+
+    alice.createGroup(groupId);
+    alice.setCredentialValidator(() => false);
+    const pkg = mallory.generateSessionKeyPackages(1)[0].keyPackageData;
+
+    let error: unknown;
+    try {
+      alice.addMember(groupId, pkg);
+    } catch (err) {
+      error = err;
+    }
+    expect(isMlsError(error) && error.code).toBe("credential_rejected");
+    expect(alice.getEpoch(groupId)).toBe(0);
+
+    const { rejected } = alice.addMembers(groupId, [pkg]);
+    expect(rejected[0].code).toBe("credential_rejected");
+  });
+
+  it("refuses a Welcome into a group with an unknown member", async () => {
+    const alice = await setupClient();
+    const bob = await setupClient();
+    const groupId = makeGroupId();
+
+    alice.createGroup(groupId);
+    const aliceIdentity = hex(alice.getMembers(groupId)[0].identity);
+    const { welcome } = alice.addMember(
+      groupId,
+      bob.generateSessionKeyPackages(1)[0].keyPackageData,
+    );
+    alice.confirmPendingCommit(groupId);
+
+    const seen: string[] = [];
+    bob.setCredentialValidator((info) => {
+      seen.push(hex(info.identity));
+      return hex(info.identity) !== aliceIdentity;
+    });
+    expect(() => bob.processWelcome(welcome)).toThrow("credential rejected");
+    expect(seen).toContain(aliceIdentity);
+  });
+
+  it("refuses an external join into a group with an unknown member", async () => {
+    const alice = await setupClient();
+    const bob = await setupClient();
+    const groupId = makeGroupId();
+
+    alice.createGroup(groupId);
+    const aliceIdentity = hex(alice.getMembers(groupId)[0].identity);
+    bob.setCredentialValidator((info) => hex(info.identity) !== aliceIdentity);
+
+    let error: unknown;
+    try {
+      bob.joinByExternalCommit(alice.exportGroupInfo(groupId));
+    } catch (err) {
+      error = err;
+    }
+    expect(isMlsError(error) && error.code).toBe("credential_rejected");
+    expect(() => bob.getEpoch(groupId)).toThrow();
+  });
+
+  it("refuses calls back into mls-wasm from the validator", async () => {
+    const alice = await setupClient();
+    const bob = await setupClient();
+    const groupId = makeGroupId();
+
+    alice.createGroup(groupId);
+    let inner: unknown;
+    alice.setCredentialValidator(() => {
+      try {
+        bob.generateSessionKeyPackages(1);
+      } catch (err) {
+        inner = err;
+      }
+      return true;
+    });
+    alice.addMember(groupId, bob.generateSessionKeyPackages(1)[0].keyPackageData);
+    expect(isMlsError(inner) && inner.code).toBe("session_busy");
+
+    // The module is still usable afterwards
+    alice.confirmPendingCommit(groupId);
+    expect(alice.getEpoch(groupId)).toBe(1);
+  });
+
+  it("refuses a commit adding a rejected member and stays on the old epoch", async () => {
+    const alice = await setupClient();
+    const bob = await setupClient();
+    const mallory = await setupClient();
+    const groupId = makeGroupId();
+
+    alice.createGroup(groupId);
+    const { welcome } = alice.addMember(
+      groupId,
+      bob.generateSessionKeyPackages(1)[0].keyPackageData,
+    );
+    alice.confirmPendingCommit(groupId);
+    bob.processWelcome(welcome);
+    const known = new Set(bob.getMembers(groupId).map((m) => hex(m.identity)));
+    bob.setCredentialValidator((info) => known.has(hex(info.identity)));
+
+    const { commit } = alice.addMember(
+      groupId,
+      mallory.generateSessionKeyPackages(1)[0].keyPackageData,
+    );
+    alice.confirmPendingCommit(groupId);
+    let error: unknown;
+    try {
+      bob.processMessage(groupId, commit);
+    } catch (err) {
+      error = err;
+    }
+    expect(isMlsError(error) && error.code).toBe("credential_rejected");
+    expect(bob.getEpoch(groupId)).toBe(1);
+    expect(bob.getMembers(groupId)).toHaveLength(2);
+  });
+});
+
+describe("MLS DID-bound credentials", () => {
+  async function setupDidClient(
+    did: string,
+    homeServer: string,
+  ): Promise<{ client: MlsClient; credential: MlsCredential; identityKey: Uint8Array }> {
+    const sodium = await ensureSodium();
+    const client = new MlsClient();
+    client.init(wasmBytes);
+    const identity = await generateIdentityKeyPair();
+    const credential = client.bindDid(
+      client.createCredential(identity.publicKey),
+      did,
+      homeServer,
+      (payload) => sodium.crypto_sign_detached(payload, identity.privateKey),
+    );
+    client.createSession(credential);
+    return { client, credential, identityKey: identity.publicKey };
+  }
+
+  it("exposes verified DID and home server for members", async () => {
+    const alice = await setupDidClient("did:cairn:alice", "alice.example");
+    const bob = await setupDidClient("did:cairn:bob", "bob.example");
+    const groupId = makeGroupId();
+
+    alice.client.createGroup(groupId);
+    const { welcome } = alice.client.addMember(
+      groupId,
+      bob.client.generateSessionKeyPackages(1)[0].keyPackageData,
+    );
+    alice.client.confirmPendingCommit(groupId);
+    bob.client.processWelcome(welcome);
+
+    const members = bob.client.getMembers(groupId);
+    expect(members[0].did).toBe("did:cairn:alice");
+    expect(members[0].homeServer).toBe("alice.example");
+    expect(members[0].identity).toEqual(alice.identityKey);
+    expect(members[1].did).toBe("did:cairn:bob");
+
+    const msg = alice.client.encryptMessage(groupId, new TextEncoder().encode("hi"));
+    expect(bob.client.processMessage(groupId, msg).senderIdentity).toEqual(
+      alice.identityKey,
+    );
+  });
+
+  it("rejects a DID identity reused with another signing key", async () => {
+    const bob = await setupDidClient("did:cairn:bob", "bob.example");
+    const mallory = new MlsClient();
+    mallory.init(wasmBytes);
+    const stolen = {
+      ...mallory.createCredential(bob.identityKey),
+      identity: bob.credential.identity,
+    };
+
+    let error: unknown;
+    try {
+      mallory.createSession(stolen);
+    } catch (err) {
+      error = err;
+    }
+    expect(isMlsError(error) && error.code).toBe("invalid_signature");
+  });
+
+  it("rejects a binding signed by the wrong key", async () => {
+    const client = new MlsClient();
+    client.init(wasmBytes);
+    const identity = await generateIdentityKeyPair();
+    const other = await generateIdentityKeyPair();
+    const sodium = await ensureSodium();
+
+    expect(() =>
+      client.bindDid(
+        client.createCredential(identity.publicKey),
+        "did:cairn:alice",
+        "alice.example",
+        (payload) => sodium.crypto_sign_detached(payload, other.privateKey),
+      ),
+    ).toThrow("identity key did not sign");
+  });
+
+  it("leaves checking a DID's key against its document to the validator", async () => {
+    const alice = await setupDidClient("did:cairn:alice", "alice.example");
+    // Mallory signs a claim to Alice's DID with her own identity key
+    const mallory = await setupDidClient("did:cairn:alice", "alice.example");
+    const groupId = makeGroupId();
+    const malloryPackage = () =>
+      mallory.client.generateSessionKeyPackages(1)[0].keyPackageData;
+
+    // The binding itself is valid, so without a validator she gets in
+    alice.client.createGroup(groupId);
+    expect(alice.client.inspectKeyPackage(malloryPackage()).did).toBe("did:cairn:alice");
+    alice.client.addMember(groupId, malloryPackage());
+    alice.client.discardPendingCommit(groupId);
+
+    // A validator resolving the DID document sees the key does not match
+    const hex = (k: Uint8Array) => Buffer.from(k).toString("hex");
+    const documents = new Map([["did:cairn:alice", alice.identityKey]]);
+    alice.client.setCredentialValidator((info) => {
+      const key = info.did === undefined ? undefined : documents.get(info.did);
+      return key !== undefined && hex(key) === hex(info.identity);
+    });
+    let error: unknown;
+    try {
+      alice.client.addMember(groupId, malloryPackage());
+    } catch (err) {
+      error = err;
+    }
+    expect(isMlsError(error) && error.code).toBe("credential_rejected");
+    expect(alice.client.hasPendingCommit(groupId)).toBe(false);
+  });
+
+  it("legacy members have no DID", async () => {
+    const alice = await setupClient();
+    const groupId = makeGroupId();
+    alice.createGroup(groupId);
+    expect(alice.getMembers(groupId)[0].did).toBeUndefined();
+  });
+
+  it("rejects an identity that is neither a bare key nor a Cairn identity", async () => {
+    const sodium = await ensureSodium();
+    const alice = await setupClient();
+    const identity = await generateIdentityKeyPair();
+    const credential = alice.createCredential(identity.publicKey);
+    const kp = alice.generateKeyPackages(credential, 1)[0].keyPackageData;
+
+    // No client will build such a package, so swap a fresh package's 32-byte
+    // identity for 33 bytes and re-sign it. Layout: version, suite, init key
+    // (37 bytes), then the leaf node up to its signature, an empty extension
+    // list and the package signature (66 + 1 + 66 bytes).
+    const concat = (...parts: Uint8Array[]) => {
+      const out = new Uint8Array(parts.reduce((n, p) => n + p.length, 0));
+      parts.reduce((at, p) => (out.set(p, at), at + p.length), 0);
+      return out;
+    };
+    const vec = (bytes: Uint8Array) =>
+      concat(
+        bytes.length < 64
+          ? Uint8Array.of(bytes.length)
+          : Uint8Array.of(0x40 | (bytes.length >> 8), bytes.length & 0xff),
+        bytes,
+      );
+    const { privateKey } = sodium.crypto_sign_seed_keypair(credential.signingPrivateKey);
+    const sign = (label: string, content: Uint8Array) =>
+      vec(
+        sodium.crypto_sign_detached(
+          concat(vec(new TextEncoder().encode(`MLS 1.0 ${label}`)), vec(content)),
+          privateKey,
+        ),
+      );
+    const identityAt = 106;
+    expect(kp.slice(identityAt, identityAt + 32)).toEqual(credential.identity);
+    const leafTbs = concat(
+      kp.slice(37, identityAt - 1),
+      vec(new Uint8Array(33).fill(7)),
+      kp.slice(identityAt + 32, kp.length - 133),
+    );
+    const kpTbs = concat(
+      kp.slice(0, 37),
+      leafTbs,
+      sign("LeafNodeTBS", leafTbs),
+      Uint8Array.of(0),
+    );
+    const forged = concat(kpTbs, sign("KeyPackageTBS", kpTbs));
+
+    const codeOf = (fn: () => unknown) => {
+      try {
+        fn();
+      } catch (err) {
+        return isMlsError(err) && err.code;
+      }
+      return undefined;
+    };
+    expect(codeOf(() => alice.inspectKeyPackage(forged))).toBe("invalid_credential");
+    const groupId = makeGroupId();
+    alice.createGroup(groupId);
+    expect(codeOf(() => alice.addMember(groupId, forged))).toBe("invalid_credential");
+    expect(alice.hasPendingCommit(groupId)).toBe(false);
+  });
+});
+
+describe("MLS KeyPackage options", () => {
+  it("a last-resort package can be used by several Welcomes", async () => {
+    const alice = await setupClient();
+    const bob = await setupClient();
+    const lastResort = bob.generateLastResortKeyPackage();
+
+    for (let i = 0; i < 2; i++) {
+      const groupId = makeGroupId();
+      alice.createGroup(groupId);
+      const { welcome } = alice.addMember(groupId, lastResort.keyPackageData);
+      alice.confirmPendingCommit(groupId);
+      expect(bob.processWelcome(welcome)).toEqual(groupId);
+    }
+  });
+
+  it("a regular package is consumed by its Welcome", async () => {
+    const alice = await setupClient();
+    const bob = await setupClient();
+    const [pkg] = bob.generateSessionKeyPackages(1, { lifetimeSeconds: 3600 });
+
+    const first = makeGroupId();
+    alice.createGroup(first);
+    bob.processWelcome(alice.addMember(first, pkg.keyPackageData).welcome);
+    alice.confirmPendingCommit(first);
+
+    const second = makeGroupId();
+    alice.createGroup(second);
+    const { welcome } = alice.addMember(second, pkg.keyPackageData);
+    alice.confirmPendingCommit(second);
+    expect(() => bob.processWelcome(welcome)).toThrow();
+  });
+
+  it("accepts custom extensions and rejects an over-long lifetime", async () => {
+    const alice = await setupClient();
+    const bob = await setupClient();
+    const [pkg] = bob.generateSessionKeyPackages(1, {
+      extensions: [{ extensionType: 0xff02, data: new Uint8Array([1]) }],
+      leafExtensions: [{ extensionType: 0xff01, data: new Uint8Array([2, 3]) }],
+    });
+
+    const groupId = makeGroupId();
+    alice.createGroup(groupId);
+    bob.processWelcome(alice.addMember(groupId, pkg.keyPackageData).welcome);
+    alice.confirmPendingCommit(groupId);
+    expect(bob.getMembers(groupId)).toHaveLength(2);
+
+    expect(() =>
+      bob.generateSessionKeyPackages(1, { lifetimeSeconds: 365 * 24 * 3600 }),
+    ).toThrow("lifetime");
+  });
+
+  it("rejects option keys it does not know", async () => {
+    const alice = await setupClient();
+    const codeOf = (fn: () => unknown) => {
+      try {
+        fn();
+      } catch (err) {
+        return isMlsError(err) && err.code;
+      }
+      return undefined;
+    };
+
+    // A typo must not silently fall back to the default
+    const keyPackageOptions = { lifetimeSeconds: 3600, lifetimeSecs: 60 };
+    expect(codeOf(() => alice.generateSessionKeyPackages(1, keyPackageOptions))).toBe(
+      "invalid_input",
+    );
+    const groupOptions = { requiredCapabilities: { extensions: [], extension: [0xff01] } };
+    expect(codeOf(() => alice.createGroup(makeGroupId(), undefined, groupOptions))).toBe(
+      "invalid_input",
+    );
+  });
+});
+
+describe("MLS KeyPackage pool", () => {
+  it("reports exactly the packages consumed by Welcomes", async () => {
+    const alice = await setupClient();
+    const bob = await setupClient();
+    const pool = bob.generateKeyPackagePool(3);
+    expect(pool).toHaveLength(3);
+    expect(bob.takeUsedKeyPackages()).toHaveLength(0);
+
+    for (const pkg of [pool[0], pool[2]]) {
+      const groupId = makeGroupId();
+      alice.createGroup(groupId);
+      bob.processWelcome(alice.addMember(groupId, pkg.keyPackageData).welcome);
+      alice.confirmPendingCommit(groupId);
+    }
+
+    expect(bob.takeUsedKeyPackages()).toEqual([pool[0].hashRef, pool[2].hashRef]);
+    expect(bob.takeUsedKeyPackages()).toHaveLength(0);
+  });
+
+  it("does not report last-resort packages", async () => {
+    const alice = await setupClient();
+    const bob = await setupClient();
+    const [pkg] = bob.generateKeyPackagePool(1, { lastResort: true });
+
+    const groupId = makeGroupId();
+    alice.createGroup(groupId);
+    bob.processWelcome(alice.addMember(groupId, pkg.keyPackageData).welcome);
+    alice.confirmPendingCommit(groupId);
+    expect(bob.takeUsedKeyPackages()).toHaveLength(0);
+  });
+});
+
+describe("MLS KeyPackage inspection", () => {
+  it("describes a valid package", async () => {
+    const bob = await setupClient();
+    const [pkg] = bob.generateKeyPackagePool(1, {
+      lastResort: true,
+      lifetimeSeconds: 3600,
+      extensions: [{ extensionType: 0xff02, data: new Uint8Array([7]) }],
+    });
+
+    const info = new MlsClient();
+    info.init(wasmBytes);
+    const described = info.inspectKeyPackage(pkg.keyPackageData);
+    expect(described.ciphersuite).toBe(1);
+    expect(described.hashRef).toEqual(pkg.hashRef);
+    expect(described.lastResort).toBe(true);
+    expect(described.lifetime.notAfter).toBeGreaterThan(Date.now() / 1000);
+    expect(described.extensions).toContainEqual({
+      extensionType: 0xff02,
+      name: "Unknown(65282)",
+      data: new Uint8Array([7]),
+    });
+    expect(described.did).toBeUndefined();
+  });
+
+  it("rejects a tampered package", async () => {
+    const bob = await setupClient();
+    const [pkg] = bob.generateKeyPackagePool(1);
+    const tampered = new Uint8Array(pkg.keyPackageData);
+    tampered[tampered.length - 5] ^= 1;
+
+    let error: unknown;
+    try {
+      bob.inspectKeyPackage(tampered);
+    } catch (err) {
+      error = err;
+    }
+    expect(isMlsError(error) && error.code).toBe("invalid_signature");
+  });
+});
+
+describe("MLS group options", () => {
+  it("sends public commits under a plaintext handshake policy", async () => {
+    const alice = await setupClient();
+    const bob = await setupClient();
+    const groupId = makeGroupId();
+    const options = { wireFormatPolicy: "purePlaintext", paddingSize: 32 } as const;
+
+    alice.createGroup(groupId, undefined, options);
+    const [bobPkg] = bob.generateSessionKeyPackages(1);
+    const { commit, welcome } = alice.addMember(groupId, bobPkg.keyPackageData);
+    alice.confirmPendingCommit(groupId);
+    // MLSMessage header: version (2 bytes) then wire format, 1 = public
+    expect(commit[3]).toBe(1);
+    bob.processWelcome(welcome, options);
+
+    const ct = alice.encryptMessage(groupId, new TextEncoder().encode("padded"));
+    expect(ct[3]).toBe(2);
+    expect(new TextDecoder().decode(bob.processMessage(groupId, ct).plaintext)).toBe("padded");
+  });
+
+  it("limits how far ahead a sender may jump", async () => {
+    const alice = await setupClient();
+    const bob = await setupClient();
+    const groupId = makeGroupId();
+    const options = { maximumForwardDistance: 2 };
+
+    alice.createGroup(groupId, undefined, options);
+    const [bobPkg] = bob.generateSessionKeyPackages(1);
+    bob.processWelcome(alice.addMember(groupId, bobPkg.keyPackageData).welcome, options);
+    alice.confirmPendingCommit(groupId);
+
+    const messages = [0, 1, 2, 3, 4].map((i) =>
+      alice.encryptMessage(groupId, new TextEncoder().encode(`m${i}`)),
+    );
+    expect(() => bob.processMessage(groupId, messages[4])).toThrow();
+    expect(new TextDecoder().decode(bob.processMessage(groupId, messages[2]).plaintext)).toBe("m2");
+  });
+
+  it("enforces required capabilities on new members", async () => {
+    const alice = await setupClient();
+    const bob = await setupClient();
+    const groupId = makeGroupId();
+
+    alice.createGroup(groupId, undefined, {
+      requiredCapabilities: { extensions: [0xff10] },
+    });
+    const [plain] = bob.generateSessionKeyPackages(1);
+    expect(() => alice.addMember(groupId, plain.keyPackageData)).toThrow();
+
+    const [capable] = bob.generateSessionKeyPackages(1, {
+      capabilities: { extensions: [0xff10] },
+    });
+    bob.processWelcome(alice.addMember(groupId, capable.keyPackageData).welcome);
+    alice.confirmPendingCommit(groupId);
+    expect(bob.getMembers(groupId)).toHaveLength(2);
+  });
+
+  it("rejects required capabilities on join", async () => {
+    const bob = await setupClient();
+    let error: unknown;
+    try {
+      bob.processWelcome(new Uint8Array(), { requiredCapabilities: {} });
+    } catch (err) {
+      error = err;
+    }
+    expect(isMlsError(error) && error.code).toBe("invalid_input");
+  });
+});
+
+describe("MLS past-epoch decryption", () => {
+  async function twoMemberGroup(maxPastEpochs?: number) {
+    const alice = await setupClient();
+    const bob = await setupClient();
+    const groupId = makeGroupId();
+    alice.createGroup(groupId, undefined, { maxPastEpochs });
+    const [bobPkg] = bob.generateSessionKeyPackages(1);
+    bob.processWelcome(alice.addMember(groupId, bobPkg.keyPackageData).welcome, {
+      maxPastEpochs,
+    });
+    alice.confirmPendingCommit(groupId);
+    return { alice, bob, groupId };
+  }
+
+  it("decrypts late messages from retained epochs", async () => {
+    const { alice, bob, groupId } = await twoMemberGroup(2);
+    const late = bob.encryptMessage(groupId, new TextEncoder().encode("late"));
+    bob.processMessage(groupId, alice.selfUpdate(groupId).commit);
+    alice.confirmPendingCommit(groupId);
+    bob.processMessage(groupId, alice.selfUpdate(groupId).commit);
+    alice.confirmPendingCommit(groupId);
+    expect(alice.getEpoch(groupId)).toBe(3);
+
+    const processed = alice.processMessage(groupId, late);
+    expect(new TextDecoder().decode(processed.plaintext)).toBe("late");
+    expect(processed.epoch).toBe(1);
+  });
+
+  it("reports the current epoch for current messages", async () => {
+    const { alice, bob, groupId } = await twoMemberGroup();
+    const ct = bob.encryptMessage(groupId, new TextEncoder().encode("now"));
+    expect(alice.processMessage(groupId, ct).epoch).toBe(alice.getEpoch(groupId));
+  });
+
+  it("rejects messages older than the window", async () => {
+    const { alice, bob, groupId } = await twoMemberGroup(0);
+    const late = bob.encryptMessage(groupId, new TextEncoder().encode("late"));
+    alice.selfUpdate(groupId);
+    alice.confirmPendingCommit(groupId);
+
+    let error: unknown;
+    try {
+      alice.processMessage(groupId, late);
+    } catch (err) {
+      error = err;
+    }
+    expect(isMlsError(error) && error.code).toBe("message_too_old");
+  });
+});
+
+describe("MLS pending commits", () => {
+  it("holds our commit until it is confirmed", async () => {
+    const alice = await setupClient();
+    const bob = await setupClient();
+    const groupId = makeGroupId();
+
+    alice.createGroup(groupId);
+    const [bobPkg] = bob.generateSessionKeyPackages(1);
+    const { welcome } = alice.addMember(groupId, bobPkg.keyPackageData);
+    expect(alice.hasPendingCommit(groupId)).toBe(true);
+    expect(() => alice.selfUpdate(groupId)).toThrow();
+
+    const details = alice.confirmPendingCommit(groupId);
+    expect(details.epoch).toBe(1);
+    expect(details.added).toHaveLength(1);
+    expect(details.pendingCommitDiscarded).toBe(false);
+    expect(alice.hasPendingCommit(groupId)).toBe(false);
+
+    bob.processWelcome(welcome);
+    const ct = alice.encryptMessage(groupId, new TextEncoder().encode("hi"));
+    expect(new TextDecoder().decode(bob.processMessage(groupId, ct).plaintext)).toBe("hi");
+  });
+
+  it("rolls back a discarded commit", async () => {
+    const alice = await setupClient();
+    const bob = await setupClient();
+    const groupId = makeGroupId();
+
+    alice.createGroup(groupId);
+    const [bobPkg] = bob.generateSessionKeyPackages(1);
+    alice.addMember(groupId, bobPkg.keyPackageData);
+    alice.discardPendingCommit(groupId);
+
+    expect(alice.hasPendingCommit(groupId)).toBe(false);
+    expect(alice.getEpoch(groupId)).toBe(0);
+    expect(alice.getMembers(groupId)).toHaveLength(1);
+
+    let error: unknown;
+    try {
+      alice.confirmPendingCommit(groupId);
+    } catch (err) {
+      error = err;
+    }
+    expect(isMlsError(error) && error.code).toBe("no_pending_commit");
+  });
+
+  it("lets a competing commit win and drops ours", async () => {
+    const alice = await setupClient();
+    const bob = await setupClient();
+    const carol = await setupClient();
+    const dave = await setupClient();
+    const groupId = makeGroupId();
+
+    alice.createGroup(groupId);
+    const [bobPkg] = bob.generateSessionKeyPackages(1);
+    bob.processWelcome(alice.addMember(groupId, bobPkg.keyPackageData).welcome);
+    alice.confirmPendingCommit(groupId);
+
+    // Both commit in epoch 1; the server orders Bob's first
+    const [carolPkg] = carol.generateSessionKeyPackages(1);
+    alice.addMember(groupId, carolPkg.keyPackageData);
+    const [davePkg] = dave.generateSessionKeyPackages(1);
+    const bobs = bob.addMember(groupId, davePkg.keyPackageData);
+    bob.confirmPendingCommit(groupId);
+    dave.processWelcome(bobs.welcome);
+
+    const processed = alice.processMessage(groupId, bobs.commit);
+    expect(processed.commit!.pendingCommitDiscarded).toBe(true);
+    expect(alice.hasPendingCommit(groupId)).toBe(false);
+    expect(alice.getEpoch(groupId)).toBe(2);
+
+    const ct = alice.encryptMessage(groupId, new TextEncoder().encode("synced"));
+    expect(new TextDecoder().decode(dave.processMessage(groupId, ct).plaintext)).toBe("synced");
+  });
+});
+
+describe("MLS leave group", () => {
+  it("removes our state once the leave is committed", async () => {
+    const alice = await setupClient();
+    const bob = await setupClient();
+    const groupId = makeGroupId();
+
+    alice.createGroup(groupId);
+    const [bobPkg] = bob.generateSessionKeyPackages(1);
+    bob.processWelcome(alice.addMember(groupId, bobPkg.keyPackageData).welcome);
+    alice.confirmPendingCommit(groupId);
+
+    const proposal = bob.leaveGroup(groupId);
+    expect(bob.isGroupActive(groupId)).toBe(false);
+
+    let error: unknown;
+    try {
+      bob.encryptMessage(groupId, new TextEncoder().encode("still here?"));
+    } catch (err) {
+      error = err;
+    }
+    expect(isMlsError(error) && error.code).toBe("left_group");
+
+    expect(alice.processMessage(groupId, proposal).messageType).toBe("proposal");
+    const { commit } = alice.commitPendingProposals(groupId);
+    alice.confirmPendingCommit(groupId);
+    expect(alice.getMembers(groupId)).toHaveLength(1);
+
+    const processed = bob.processMessage(groupId, commit);
+    expect(processed.commitDetails?.selfRemoved).toBe(true);
+    error = undefined;
+    try {
+      bob.getEpoch(groupId);
+    } catch (err) {
+      error = err;
+    }
+    expect(isMlsError(error) && error.code).toBe("group_not_found");
+  });
+
+  it("can rejoin by external commit after leaving", async () => {
+    const alice = await setupClient();
+    const bob = await setupClient();
+    const groupId = makeGroupId();
+
+    alice.createGroup(groupId);
+    const [bobPkg] = bob.generateSessionKeyPackages(1);
+    bob.processWelcome(alice.addMember(groupId, bobPkg.keyPackageData).welcome);
+    alice.confirmPendingCommit(groupId);
+
+    bob.leaveGroup(groupId);
+    const { commit } = bob.joinByExternalCommit(alice.exportGroupInfo(groupId));
+    alice.processMessage(groupId, commit);
+    expect(bob.isGroupActive(groupId)).toBe(true);
+
+    const ct = bob.encryptMessage(groupId, new TextEncoder().encode("back again"));
+    expect(new TextDecoder().decode(alice.processMessage(groupId, ct).plaintext)).toBe(
+      "back again",
+    );
+  });
+});
+
+describe("MLS member lookup", () => {
+  it("finds and removes every device of an identity", async () => {
+    const alice = await setupClient();
+    const carol = await setupClient();
+    const bobIdentity = await generateIdentityKeyPair();
+    const bobDevices = [new MlsClient(), new MlsClient()];
+    for (const device of bobDevices) {
+      device.init(wasmBytes);
+      device.createSession(device.createCredential(bobIdentity.publicKey));
+    }
+    const groupId = makeGroupId();
+
+    alice.createGroup(groupId);
+    alice.addMembers(groupId, [
+      ...bobDevices.map((d) => d.generateSessionKeyPackages(1)[0].keyPackageData),
+      carol.generateSessionKeyPackages(1)[0].keyPackageData,
+    ]);
+    alice.confirmPendingCommit(groupId);
+
+    const bobIdentityBytes = bobIdentity.publicKey;
+    expect(alice.findMember(groupId, bobIdentityBytes)?.devices).toHaveLength(2);
+    expect(alice.findMember(groupId, new Uint8Array(32))).toBeUndefined();
+
+    let error: unknown;
+    try {
+      alice.removeMembersByIdentity(groupId, [bobIdentityBytes, new Uint8Array(32)]);
+    } catch (err) {
+      error = err;
+    }
+    expect(isMlsError(error) && error.code).toBe("member_not_found");
+    expect(alice.hasPendingCommit(groupId)).toBe(false);
+
+    alice.removeMembersByIdentity(groupId, [bobIdentityBytes]);
+    const details = alice.confirmPendingCommit(groupId);
+    expect(details.removed).toHaveLength(2);
+    expect(alice.getMembers(groupId)).toHaveLength(2);
+  });
+});
+
+describe("MLS multi-device members", () => {
+  it("groups a user's devices and adds them together", async () => {
+    const sodium = await ensureSodium();
+    const alice = await setupClient();
+    const bobIdentity = await generateIdentityKeyPair();
+    const bobDevices = ["phone", "laptop"].map((id) => {
+      const client = new MlsClient();
+      client.init(wasmBytes);
+      client.createSession(
+        client.bindDid(
+          client.createCredential(bobIdentity.publicKey),
+          "did:cairn:bob",
+          "bob.example",
+          (payload) => sodium.crypto_sign_detached(payload, bobIdentity.privateKey),
+          { id, label: id === "phone" ? "Bob's phone" : undefined },
+        ),
+      );
+      return client;
+    });
+    const groupId = makeGroupId();
+    alice.createGroup(groupId);
+
+    const packages = bobDevices.map(
+      (d) => d.generateSessionKeyPackages(1)[0].keyPackageData,
+    );
+    expect(alice.inspectKeyPackage(packages[0]).deviceId).toBe("phone");
+    const { welcome } = alice.addUserDevices(groupId, packages);
+    expect(alice.confirmPendingCommit(groupId).added).toHaveLength(2);
+    for (const device of bobDevices) device.processWelcome(welcome);
+
+    const members = bobDevices[1].getMembers(groupId);
+    expect(members).toHaveLength(2);
+    const bob = members[1];
+    expect(bob.did).toBe("did:cairn:bob");
+    expect(bob.devices.map((d) => d.deviceId)).toEqual(["phone", "laptop"]);
+    expect(bob.devices.map((d) => d.deviceLabel)).toEqual(["Bob's phone", ""]);
+    expect(members[0].devices[0].deviceId).toBeUndefined();
+
+    // A device already in the group can't be added again
+    let error: unknown;
+    try {
+      alice.addUserDevices(groupId, [
+        bobDevices[0].generateSessionKeyPackages(1)[0].keyPackageData,
+      ]);
+    } catch (err) {
+      error = err;
+    }
+    expect(isMlsError(error) && error.code).toBe("invalid_key_package");
+    expect(alice.hasPendingCommit(groupId)).toBe(false);
+  });
+
+  it("refuses a second leaf for a device already in the group", async () => {
+    const sodium = await ensureSodium();
+    const alice = await setupClient();
+    const bobIdentity = await generateIdentityKeyPair();
+    // Each call is a fresh session, so a fresh signing key, for the same device
+    const phonePackage = () => {
+      const client = new MlsClient();
+      client.init(wasmBytes);
+      client.createSession(
+        client.bindDid(
+          client.createCredential(bobIdentity.publicKey),
+          "did:cairn:bob",
+          "bob.example",
+          (payload) => sodium.crypto_sign_detached(payload, bobIdentity.privateKey),
+          { id: "phone" },
+        ),
+      );
+      return client.generateSessionKeyPackages(1)[0].keyPackageData;
+    };
+    const groupId = makeGroupId();
+    alice.createGroup(groupId);
+    alice.addMember(groupId, phonePackage());
+    alice.confirmPendingCommit(groupId);
+
+    const codeOf = (fn: () => unknown) => {
+      try {
+        fn();
+      } catch (err) {
+        return isMlsError(err) && err.code;
+      }
+      return undefined;
+    };
+    expect(codeOf(() => alice.addMember(groupId, phonePackage()))).toBe(
+      "invalid_key_package",
+    );
+    expect(codeOf(() => alice.addUserDevices(groupId, [phonePackage()]))).toBe(
+      "invalid_key_package",
+    );
+    const { commit, rejected } = alice.addMembers(groupId, [phonePackage()]);
+    expect(commit).toBeUndefined();
+    expect(rejected.map((r) => r.code)).toEqual(["invalid_key_package"]);
+    expect(alice.hasPendingCommit(groupId)).toBe(false);
+    expect(alice.findMember(groupId, bobIdentity.publicKey)?.devices).toHaveLength(1);
+  });
+});
+
+describe("MLS group fingerprints", () => {
+  it("gives members in the same epoch the same safety number", async () => {
+    const alice = await setupClient();
+    const bob = await setupClient();
+    const groupId = makeGroupId();
+
+    alice.createGroup(groupId);
+    const [bobPkg] = bob.generateSessionKeyPackages(1);
+    bob.processWelcome(alice.addMember(groupId, bobPkg.keyPackageData).welcome);
+    alice.confirmPendingCommit(groupId);
+
+    const authenticator = alice.getEpochAuthenticator(groupId);
+    expect(bob.getEpochAuthenticator(groupId)).toEqual(authenticator);
+
+    const numeric = alice.groupFingerprint(authenticator);
+    expect(numeric).toMatch(/^\d{5}( \d{5}){5}$/);
+    expect(bob.groupFingerprint(bob.getEpochAuthenticator(groupId))).toBe(numeric);
+    expect(alice.groupFingerprint(authenticator, "emoji").split(" ")).toHaveLength(8);
+
+    const { commit } = alice.selfUpdate(groupId);
+    alice.confirmPendingCommit(groupId);
+    expect(alice.groupFingerprint(alice.getEpochAuthenticator(groupId))).not.toBe(numeric);
+    bob.processMessage(groupId, commit);
+    expect(bob.getEpochAuthenticator(groupId)).toEqual(alice.getEpochAuthenticator(groupId));
+  });
+});
+
+describe("MLS pre-shared keys", () => {
+  it("commits an external PSK only members holding it can process", async () => {
+    const alice = await setupClient();
+    const bob = await setupClient();
+    const carol = await setupClient();
+    const groupId = makeGroupId();
+
+    alice.createGroup(groupId);
+    const [bobPkg] = bob.generateSessionKeyPackages(1);
+    const [carolPkg] = carol.generateSessionKeyPackages(1);
+    const { welcome } = alice.addMembers(groupId, [
+      bobPkg.keyPackageData,
+      carolPkg.keyPackageData,
+    ]);
+    alice.confirmPendingCommit(groupId);
+    bob.processWelcome(welcome!);
+    carol.processWelcome(welcome!);
+
+    const pskId = new TextEncoder().encode("channel-secret");
+    const secret = crypto.getRandomValues(new Uint8Array(32));
+    alice.registerExternalPsk(pskId, secret);
+    bob.registerExternalPsk(pskId, secret);
+
+    const { proposal } = alice.proposeExternalPsk(groupId, pskId);
+    bob.processMessage(groupId, proposal);
+    carol.processMessage(groupId, proposal);
+    const { commit } = alice.commitPendingProposals(groupId);
+    alice.confirmPendingCommit(groupId);
+
+    bob.processMessage(groupId, commit);
+    expect(bob.getEpoch(groupId)).toBe(alice.getEpoch(groupId));
+
+    let error: unknown;
+    try {
+      carol.processMessage(groupId, commit);
+    } catch (err) {
+      error = err;
+    }
+    expect(isMlsError(error) && error.code).toBe("psk_not_found");
+  });
+
+  it("branches a group that only parent members can join", async () => {
+    const alice = await setupClient();
+    const bob = await setupClient();
+    const parentId = makeGroupId();
+    const branchId = makeGroupId();
+
+    alice.createGroup(parentId);
+    const [bobPkg] = bob.generateSessionKeyPackages(1);
+    bob.processWelcome(alice.addMember(parentId, bobPkg.keyPackageData).welcome);
+    alice.confirmPendingCommit(parentId);
+
+    const [branchPkg] = bob.generateSessionKeyPackages(1);
+    const { welcome } = alice.branchGroup(parentId, branchId, [
+      branchPkg.keyPackageData,
+    ]);
+    alice.confirmPendingCommit(branchId);
+
+    expect(bob.processBranchWelcome(welcome, parentId)).toEqual(branchId);
+    const context = new Uint8Array(0);
+    expect(bob.exportSecret(branchId, "cairn-voice", context, 16)).toEqual(
+      alice.exportSecret(branchId, "cairn-voice", context, 16),
+    );
+  });
+});
+
+describe("MLS errors", () => {
+  function thrown(fn: () => unknown): unknown {
+    try {
+      fn();
+    } catch (err) {
+      return err;
+    }
+    throw new Error("expected an error");
+  }
+
+  it("carries a stable code and the group ID", async () => {
+    const alice = await setupClient();
+    const groupId = makeGroupId();
+
+    const err = thrown(() => alice.getEpoch(groupId));
+    expect(isMlsError(err)).toBe(true);
+    if (!isMlsError(err)) return;
+    expect(err.code).toBe("group_not_found");
+    expect(err.groupId).toEqual(groupId);
+  });
+
+  it("reports the message epoch for messages from a future epoch", async () => {
+    const alice = await setupClient();
+    const bob = await setupClient();
+    const groupId = makeGroupId();
+
+    alice.createGroup(groupId);
+    const bobPkgs = bob.generateSessionKeyPackages(1);
+    bob.processWelcome(alice.addMember(groupId, bobPkgs[0].keyPackageData).welcome);
+    alice.confirmPendingCommit(groupId);
+
+    // Bob hasn't seen this commit yet
+    const { commit } = alice.selfUpdate(groupId);
+    alice.confirmPendingCommit(groupId);
+    const ct = alice.encryptMessage(groupId, new TextEncoder().encode("later"));
+
+    const err = thrown(() => bob.processMessage(groupId, ct));
+    expect(isMlsError(err) && err.code).toBe("wrong_epoch");
+    expect(isMlsError(err) && err.epoch).toBe(2);
+
+    bob.processMessage(groupId, commit);
+    expect(new TextDecoder().decode(bob.processMessage(groupId, ct).plaintext)).toBe("later");
+
+    const replay = thrown(() => bob.processMessage(groupId, ct));
+    expect(isMlsError(replay) && replay.code).toBe("message_too_old");
+  });
+
+  it("distinguishes malformed input and unknown Welcomes", async () => {
+    const alice = await setupClient();
+    const bob = await setupClient();
+    const carol = await setupClient();
+    const groupId = makeGroupId();
+
+    alice.createGroup(groupId);
+    const bobPkgs = bob.generateSessionKeyPackages(1);
+    const { welcome } = alice.addMember(groupId, bobPkgs[0].keyPackageData);
+    alice.confirmPendingCommit(groupId);
+
+    const malformed = thrown(() => alice.processMessage(groupId, new Uint8Array([1, 2, 3])));
+    expect(isMlsError(malformed) && malformed.code).toBe("malformed_message");
+
+    const foreign = thrown(() => carol.processWelcome(welcome));
+    expect(isMlsError(foreign) && foreign.code).toBe("no_matching_key_package");
+  });
+
+  it("client-side precondition errors are not MlsErrors", () => {
+    const client = new MlsClient();
+    client.init(wasmBytes);
+    expect(isMlsError(thrown(() => client.getEpoch(makeGroupId())))).toBe(false);
+  });
 });
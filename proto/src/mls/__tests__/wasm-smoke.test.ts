@@ -19,8 +19,11 @@ describe("MLS WASM smoke test", () => {
     const raw = supported_ciphersuites();
     const suites = JSON.parse(raw);
     expect(Array.isArray(suites)).toBe(true);
-    expect(suites).toHaveLength(1);
-    expect(suites[0].name).toBe("MLS_128_DHKEMX25519_AES128GCM_SHA256_Ed25519");
-    expect(suites[0].value).toBe(1);
+    expect(suites.map((s: { value: number }) => s.value).sort()).toEqual([1, 2, 3]);
+
+    const defaults = suites.filter((s: { default: boolean }) => s.default);
+    expect(defaults).toHaveLength(1);
+    expect(defaults[0].name).toBe("MLS_128_DHKEMX25519_AES128GCM_SHA256_Ed25519");
+    expect(defaults[0].value).toBe(1);
   });
 });
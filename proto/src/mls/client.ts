@@ -7,34 +7,93 @@
  * Group operations require an active session (call `createSession()` first).
  * Sessions hold cryptographic state (provider, signer) needed for MLS group
  * operations including KeyPackage generation and Welcome processing.
+ *
+ * Failures inside WASM throw an `MlsError` with a stable `code`
+ * (see `isMlsError()`); branch on the code rather than the message.
  */
 import initWasm, {
   initSync,
   create_credential,
+  cairn_identity_payload,
+  create_cairn_identity,
   import_signing_key,
   generate_key_package,
+  inspect_key_package,
   create_session,
   destroy_session,
+  export_session,
+  import_session,
   session_generate_key_package,
+  generate_key_packages,
+  take_used_key_packages,
+  set_credential_validator,
   create_mls_group,
   add_member,
+  add_members,
+  add_user_devices,
   remove_member,
+  remove_members_by_identity,
+  self_update,
   process_welcome,
+  process_branch_welcome,
+  join_by_external_commit,
   encrypt_message,
   process_group_message,
+  propose_add,
+  propose_remove,
+  propose_self_update,
+  leave_group,
+  commit_pending_proposals,
+  register_external_psk,
+  remove_external_psk,
+  propose_external_psk,
+  branch_group,
+  get_pending_proposals,
+  confirm_pending_commit,
+  discard_pending_commit,
+  has_pending_commit,
+  is_group_active,
   get_epoch,
   get_members,
+  find_member,
+  export_group_info,
+  export_ratchet_tree,
+  export_secret,
+  epoch_authenticator,
+  group_fingerprint,
+  epochs_since_self_update,
   type WasmCredentialBundle,
   type WasmKeyPackageResult,
   type WasmAddMemberResult,
+  type WasmAddMembersResult,
   type WasmProcessedMessage,
+  type WasmProposalResult,
+  type WasmCommitResult,
+  type WasmExternalJoinResult,
 } from "../../mls-wasm/pkg/mls_wasm.js";
 import type {
   MlsCredential,
   MlsKeyPackage,
+  MlsKeyPackageOptions,
+  MlsGroupOptions,
+  MlsPooledKeyPackage,
+  MlsKeyPackageInfo,
+  MlsExtensionInfo,
   MlsAddMemberResult,
+  MlsAddMembersResult,
   MlsProcessedMessage,
   MlsGroupMember,
+  MlsMemberDevice,
+  MlsDeviceInfo,
+  MlsFingerprintFormat,
+  MlsStorage,
+  MlsCredentialValidator,
+  MlsProposalResult,
+  MlsCommitResult,
+  MlsPendingProposal,
+  MlsExternalJoinResult,
+  MlsCommitDetails,
+  MlsMemberChange,
 } from "./types.js";
 
 function resultToKeyPackage(result: WasmKeyPackageResult): MlsKeyPackage {
@@ -46,11 +105,91 @@ function resultToKeyPackage(result: WasmKeyPackageResult): MlsKeyPackage {
   return kp;
 }
 
+function resultToProposal(result: WasmProposalResult): MlsProposalResult {
+  const out: MlsProposalResult = {
+    proposal: new Uint8Array(result.proposal),
+    proposalRef: new Uint8Array(result.proposalRef),
+  };
+  result.free();
+  return out;
+}
+
+function resultToCommit(result: WasmCommitResult): MlsCommitResult {
+  const out: MlsCommitResult = {
+    commit: new Uint8Array(result.commit),
+    welcome: result.welcome ? new Uint8Array(result.welcome) : undefined,
+    groupInfo: new Uint8Array(result.groupInfo),
+  };
+  result.free();
+  return out;
+}
+
+function parseCommitDetails(json: string): MlsCommitDetails {
+  type RawChange = { index: number; identity: number[] };
+  const raw = JSON.parse(json) as {
+    epoch: number;
+    added: RawChange[];
+    removed: RawChange[];
+    updated: RawChange[];
+    extensions_changed: number[];
+    self_removed: boolean;
+    pending_commit_discarded: boolean;
+  };
+  const toChange = (c: RawChange): MlsMemberChange => ({
+    index: c.index,
+    identity: new Uint8Array(c.identity),
+  });
+  return {
+    epoch: raw.epoch,
+    added: raw.added.map(toChange),
+    removed: raw.removed.map(toChange),
+    updated: raw.updated.map(toChange),
+    extensionsChanged: raw.extensions_changed,
+    selfRemoved: raw.self_removed,
+    pendingCommitDiscarded: raw.pending_commit_discarded,
+  };
+}
+
+type RawGroupMember = {
+  identity: number[];
+  did: string | null;
+  home_server: string | null;
+  devices: Array<{
+    index: number;
+    signature_key: number[];
+    device_id: string | null;
+    device_label: string | null;
+  }>;
+};
+
+function toGroupMember(m: RawGroupMember): MlsGroupMember {
+  const member: MlsGroupMember = {
+    identity: new Uint8Array(m.identity),
+    devices: m.devices.map((d) => {
+      const device: MlsMemberDevice = {
+        index: d.index,
+        signatureKey: new Uint8Array(d.signature_key),
+      };
+      if (d.device_id !== null) {
+        device.deviceId = d.device_id;
+        device.deviceLabel = d.device_label ?? "";
+      }
+      return device;
+    }),
+  };
+  if (m.did !== null && m.home_server !== null) {
+    member.did = m.did;
+    member.homeServer = m.home_server;
+  }
+  return member;
+}
+
 function bundleToCredential(bundle: WasmCredentialBundle): MlsCredential {
   const credential: MlsCredential = {
     identity: new Uint8Array(bundle.identity),
     signingPublicKey: new Uint8Array(bundle.signingPublicKey),
     signingPrivateKey: new Uint8Array(bundle.signingPrivateKey),
+    ciphersuite: bundle.ciphersuite,
   };
   bundle.free();
   return credential;
@@ -59,6 +198,7 @@ function bundleToCredential(bundle: WasmCredentialBundle): MlsCredential {
 export class MlsClient {
   private initialized = false;
   private sessionId: number | null = null;
+  private credentialValidator: MlsCredentialValidator | undefined;
 
   /**
    * Initialize the WASM module synchronously. Must be called before any other method.
@@ -84,12 +224,63 @@ export class MlsClient {
   // ==================== Standalone Credential Operations ====================
 
   /**
-   * Create an MLS credential with a newly generated Ed25519 signing key pair.
+   * Bind a credential to the user's DID and home server. The identity key
+   * signs the DID, domain and MLS signing key, so the claim can't be moved to
+   * another leaf or identity key. It does not prove the DID is the identity
+   * key's: members' credential validators must resolve the DID and compare
+   * keys (see `setCredentialValidator()`). Use the returned credential in
+   * place of the original (e.g. `createSession()`).
+   *
+   * @param credential - Credential from `createCredential()` or `importSigningKey()`.
+   * @param did - The user's `did:cairn:` DID.
+   * @param homeServer - The user's home server domain.
+   * @param sign - Signs with the Ed25519 identity private key
+   *   (e.g. `(msg) => sodium.crypto_sign_detached(msg, identity.privateKey)`).
+   * @param device - Which of the user's devices this credential is for.
+   *   Each device needs its own credential (signing key); members see the
+   *   devices grouped under one user in `getMembers()`.
+   */
+  bindDid(
+    credential: MlsCredential,
+    did: string,
+    homeServer: string,
+    sign: (payload: Uint8Array) => Uint8Array,
+    device?: MlsDeviceInfo,
+  ): MlsCredential {
+    this.ensureInitialized();
+    const identityKey = credential.identity;
+    const payload = cairn_identity_payload(
+      identityKey,
+      did,
+      homeServer,
+      credential.signingPublicKey,
+      device?.id,
+      device?.label,
+    );
+    const identity = create_cairn_identity(
+      identityKey,
+      did,
+      homeServer,
+      credential.signingPublicKey,
+      sign(new Uint8Array(payload)),
+      device?.id,
+      device?.label,
+    );
+    return { ...credential, identity: new Uint8Array(identity) };
+  }
+
+  /**
+   * Create an MLS credential with a newly generated signing key pair.
    * @param identityPublicKey - The user's Ed25519 identity public key (32 bytes).
+   * @param ciphersuite - Ciphersuite the signing key is generated for
+   *   (defaults to the X25519/Ed25519 suite).
    */
-  createCredential(identityPublicKey: Uint8Array): MlsCredential {
+  createCredential(
+    identityPublicKey: Uint8Array,
+    ciphersuite?: number,
+  ): MlsCredential {
     this.ensureInitialized();
-    return bundleToCredential(create_credential(identityPublicKey));
+    return bundleToCredential(create_credential(identityPublicKey, ciphersuite));
   }
 
   /**
@@ -97,15 +288,24 @@ export class MlsClient {
    * @param identityPublicKey - The user's Ed25519 identity public key (32 bytes).
    * @param signingPrivateKey - Ed25519 private key (32-byte seed or 64-byte libsodium format).
    * @param signingPublicKey - Ed25519 public key (32 bytes).
+   * @param ciphersuite - Ciphersuite the key belongs to (defaults to the
+   *   X25519/Ed25519 suite; P-256 suites take a 32-byte scalar and a
+   *   65-byte uncompressed public key).
    */
   importSigningKey(
     identityPublicKey: Uint8Array,
     signingPrivateKey: Uint8Array,
     signingPublicKey: Uint8Array,
+    ciphersuite?: number,
   ): MlsCredential {
     this.ensureInitialized();
     return bundleToCredential(
-      import_signing_key(identityPublicKey, signingPrivateKey, signingPublicKey),
+      import_signing_key(
+        identityPublicKey,
+        signingPrivateKey,
+        signingPublicKey,
+        ciphersuite,
+      ),
     );
   }
 
@@ -114,7 +314,11 @@ export class MlsClient {
    * These cannot be used for Welcome processing since the init keys are ephemeral.
    * For group operations, use `generateSessionKeyPackages()` instead.
    */
-  generateKeyPackages(credential: MlsCredential, count = 50): MlsKeyPackage[] {
+  generateKeyPackages(
+    credential: MlsCredential,
+    count = 50,
+    options?: MlsKeyPackageOptions,
+  ): MlsKeyPackage[] {
     this.ensureInitialized();
     const packages: MlsKeyPackage[] = [];
     for (let i = 0; i < count; i++) {
@@ -124,6 +328,8 @@ export class MlsClient {
             credential.identity,
             credential.signingPrivateKey,
             credential.signingPublicKey,
+            credential.ciphersuite,
+            options,
           ),
         ),
       );
@@ -131,6 +337,59 @@ export class MlsClient {
     return packages;
   }
 
+  /**
+   * Validate a KeyPackage and describe it; no session needed. Throws
+   * `MlsError` if a signature (or DID binding) is invalid or the package
+   * has expired. Check the identity before passing it to `addMember()`.
+   */
+  inspectKeyPackage(keyPackageTls: Uint8Array): MlsKeyPackageInfo {
+    this.ensureInitialized();
+    type RawExtension = { extension_type: number; name: string; data: number[] | null };
+    const raw = JSON.parse(inspect_key_package(keyPackageTls)) as {
+      ciphersuite: number;
+      identity: number[];
+      did: string | null;
+      home_server: string | null;
+      device_id: string | null;
+      device_label: string | null;
+      signature_key: number[];
+      lifetime: { not_before: number; not_after: number };
+      last_resort: boolean;
+      capabilities: MlsKeyPackageInfo["capabilities"];
+      extensions: RawExtension[];
+      leaf_extensions: RawExtension[];
+      hash_ref: number[];
+    };
+    const extension = (e: RawExtension): MlsExtensionInfo => {
+      const out: MlsExtensionInfo = { extensionType: e.extension_type, name: e.name };
+      if (e.data !== null) out.data = new Uint8Array(e.data);
+      return out;
+    };
+    const info: MlsKeyPackageInfo = {
+      ciphersuite: raw.ciphersuite,
+      identity: new Uint8Array(raw.identity),
+      signatureKey: new Uint8Array(raw.signature_key),
+      lifetime: {
+        notBefore: raw.lifetime.not_before,
+        notAfter: raw.lifetime.not_after,
+      },
+      lastResort: raw.last_resort,
+      capabilities: raw.capabilities,
+      extensions: raw.extensions.map(extension),
+      leafExtensions: raw.leaf_extensions.map(extension),
+      hashRef: new Uint8Array(raw.hash_ref),
+    };
+    if (raw.did !== null && raw.home_server !== null) {
+      info.did = raw.did;
+      info.homeServer = raw.home_server;
+    }
+    if (raw.device_id !== null) {
+      info.deviceId = raw.device_id;
+      info.deviceLabel = raw.device_label ?? "";
+    }
+    return info;
+  }
+
   // ==================== Session Management ====================
 
   /**
@@ -139,8 +398,10 @@ export class MlsClient {
    * which is required for Welcome processing (init keys must persist).
    *
    * @param credential - The MLS credential to use for this session.
+   * @param storage - Optional store that group state is written through to.
+   *   Reopening a session over the same store restores its groups.
    */
-  createSession(credential: MlsCredential): void {
+  createSession(credential: MlsCredential, storage?: MlsStorage): void {
     this.ensureInitialized();
     if (this.sessionId !== null) {
       destroy_session(this.sessionId);
@@ -149,7 +410,10 @@ export class MlsClient {
       credential.identity,
       credential.signingPrivateKey,
       credential.signingPublicKey,
+      storage,
+      credential.ciphersuite,
     );
+    set_credential_validator(this.sessionId, this.credentialValidator);
   }
 
   /**
@@ -162,38 +426,149 @@ export class MlsClient {
     }
   }
 
+  /**
+   * Export the current session's MLS state as an encrypted, versioned blob.
+   * Only available for in-memory sessions (no `storage` passed to `createSession()`).
+   * The blob can be persisted (IndexedDB, desktop keychain) and restored with
+   * `importSession()` without rejoining any groups.
+   *
+   * @param key - 32-byte symmetric key used to seal the snapshot.
+   */
+  exportSession(key: Uint8Array): Uint8Array {
+    this.ensureSession();
+    return new Uint8Array(export_session(this.sessionId!, key));
+  }
+
+  /**
+   * Restore a session from a blob produced by `exportSession()`.
+   * Replaces the current session, if any.
+   *
+   * @param blob - Encrypted session snapshot.
+   * @param key - The 32-byte key the snapshot was sealed with.
+   */
+  importSession(blob: Uint8Array, key: Uint8Array): void {
+    this.ensureInitialized();
+    const sessionId = import_session(blob, key);
+    if (this.sessionId !== null) {
+      destroy_session(this.sessionId);
+    }
+    this.sessionId = sessionId;
+    set_credential_validator(this.sessionId, this.credentialValidator);
+  }
+
+  /**
+   * Register a validator for credentials entering or changing in a group:
+   * the members of a group joined by Welcome or external commit, key
+   * packages passed to `addMember()`/`addMembers()`/`proposeAdd()`, and the
+   * new leaves in incoming proposals and commits. A refusal throws `MlsError` with code
+   * `credential_rejected` and nothing is applied. Use it to check that each
+   * identity is a real account key. For DID-bound credentials it MUST
+   * resolve the DID document for `info.did` and compare its key to
+   * `info.identity`: the binding only proves the identity key signed the
+   * claim, and anyone can claim any DID. Kept across `createSession()` and
+   * `importSession()`; pass `undefined` to remove it.
+   *
+   * The validator runs synchronously inside the MLS call. It must not call
+   * any `MlsClient` session method (on this client or another): those fail
+   * with `session_busy` while the validator runs.
+   */
+  setCredentialValidator(validator: MlsCredentialValidator | undefined): void {
+    this.credentialValidator = validator;
+    if (this.sessionId !== null) {
+      set_credential_validator(this.sessionId, validator);
+    }
+  }
+
   /**
    * Generate MLS KeyPackages within the current session.
    * Init private keys are stored in the session's provider, enabling
    * Welcome processing for groups the user is invited to.
    */
-  generateSessionKeyPackages(count = 50): MlsKeyPackage[] {
+  generateSessionKeyPackages(
+    count = 50,
+    options?: MlsKeyPackageOptions,
+  ): MlsKeyPackage[] {
     this.ensureSession();
     const packages: MlsKeyPackage[] = [];
     for (let i = 0; i < count; i++) {
       packages.push(
-        resultToKeyPackage(session_generate_key_package(this.sessionId!)),
+        resultToKeyPackage(
+          session_generate_key_package(this.sessionId!, options),
+        ),
       );
     }
     return packages;
   }
 
+  /**
+   * Generate KeyPackages for upload whose init private keys never leave the
+   * session. Prefer this over `generateSessionKeyPackages()`.
+   */
+  generateKeyPackagePool(
+    count = 50,
+    options?: MlsKeyPackageOptions,
+  ): MlsPooledKeyPackage[] {
+    this.ensureSession();
+    const raw = JSON.parse(
+      generate_key_packages(this.sessionId!, count, options),
+    ) as Array<{ key_package: number[]; hash_ref: number[] }>;
+    return raw.map((p) => ({
+      keyPackageData: new Uint8Array(p.key_package),
+      hashRef: new Uint8Array(p.hash_ref),
+    }));
+  }
+
+  /**
+   * Hash refs of this session's KeyPackages consumed by processed Welcomes
+   * since the last call (the list is then cleared). Replenish the server pool
+   * with exactly this many. Last-resort packages are never reported.
+   */
+  takeUsedKeyPackages(): Uint8Array[] {
+    this.ensureSession();
+    const raw = JSON.parse(take_used_key_packages(this.sessionId!)) as number[][];
+    return raw.map((r) => new Uint8Array(r));
+  }
+
+  /**
+   * Generate a last-resort KeyPackage within the current session. Upload it
+   * alongside the regular pool; it can be used by any number of Welcomes.
+   */
+  generateLastResortKeyPackage(
+    options?: Omit<MlsKeyPackageOptions, "lastResort">,
+  ): MlsKeyPackage {
+    return this.generateSessionKeyPackages(1, { ...options, lastResort: true })[0];
+  }
+
   // ==================== Group Operations ====================
 
   /**
    * Create a new MLS group. The caller becomes the sole member.
    * @param groupId - Unique group identifier (e.g., channel UUID as bytes).
+   * @param ciphersuite - Group ciphersuite; defaults to the session's and
+   *   must use the same signature scheme as the session's credential.
+   * @param options - Epoch retention, sender ratchet tolerance, padding,
+   *   wire format policy and required capabilities.
    */
-  createGroup(groupId: Uint8Array): void {
+  createGroup(
+    groupId: Uint8Array,
+    ciphersuite?: number,
+    options?: MlsGroupOptions,
+  ): void {
     this.ensureSession();
-    create_mls_group(this.sessionId!, groupId);
+    create_mls_group(this.sessionId!, groupId, ciphersuite, options);
   }
 
   /**
-   * Add a member to an existing MLS group.
+   * Add a member to an existing MLS group. Like every commit we make, the
+   * commit stays pending: upload it, then call `confirmPendingCommit()` once
+   * the server accepts it (and only then send the Welcome), or
+   * `discardPendingCommit()` if it is refused.
    * @param groupId - The group to add the member to.
    * @param keyPackageTls - TLS-serialized KeyPackage of the member to add.
-   * @returns Commit (broadcast to existing members) and Welcome (send to new member).
+   * @returns Commit (broadcast to existing members), Welcome (send to new member)
+   *   and the new epoch's GroupInfo (upload to the server).
+   * @throws MlsError with code `invalid_key_package` if the package's
+   *   signature key or device is already in the group.
    */
   addMember(
     groupId: Uint8Array,
@@ -208,6 +583,35 @@ export class MlsClient {
     const out: MlsAddMemberResult = {
       commit: new Uint8Array(result.commit),
       welcome: new Uint8Array(result.welcome),
+      groupInfo: new Uint8Array(result.groupInfo),
+    };
+    result.free();
+    return out;
+  }
+
+  /**
+   * Add several members in a single commit (one epoch, one Welcome).
+   * KeyPackages that fail validation, or repeat a signature key or device
+   * already in the group or earlier in the list, are left out and reported in
+   * `rejected` by their position in `keyPackages`; the rest are still added.
+   * If none are valid, no commit is made and `commit`/`welcome` are absent.
+   * The commit stays pending until confirmed or discarded.
+   */
+  addMembers(
+    groupId: Uint8Array,
+    keyPackages: Uint8Array[],
+  ): MlsAddMembersResult {
+    this.ensureSession();
+    const result: WasmAddMembersResult = add_members(
+      this.sessionId!,
+      groupId,
+      keyPackages,
+    );
+    const out: MlsAddMembersResult = {
+      commit: result.commit ? new Uint8Array(result.commit) : undefined,
+      welcome: result.welcome ? new Uint8Array(result.welcome) : undefined,
+      groupInfo: result.groupInfo ? new Uint8Array(result.groupInfo) : undefined,
+      rejected: JSON.parse(result.rejected) as MlsAddMembersResult["rejected"],
     };
     result.free();
     return out;
@@ -217,45 +621,190 @@ export class MlsClient {
    * Remove a member from an MLS group.
    * @param groupId - The group to remove the member from.
    * @param leafIndex - The leaf node index of the member to remove.
-   * @returns TLS-serialized Commit message (broadcast to remaining members).
+   *   The commit stays pending until confirmed or discarded.
+   * @returns Commit (broadcast to remaining members) and the new GroupInfo.
+   * @throws MlsError with code `member_not_found` if the leaf is empty or out
+   *   of range, or `invalid_input` if it is our own (use `leaveGroup()`).
    */
-  removeMember(groupId: Uint8Array, leafIndex: number): Uint8Array {
+  removeMember(groupId: Uint8Array, leafIndex: number): MlsCommitResult {
     this.ensureSession();
-    return new Uint8Array(
-      remove_member(this.sessionId!, groupId, leafIndex),
+    return resultToCommit(remove_member(this.sessionId!, groupId, leafIndex));
+  }
+
+  /**
+   * Add all of one user's devices in a single commit, so they never appear
+   * with only some of them. All or nothing: every package must be valid,
+   * share one identity and name a device not yet in the group.
+   * The commit stays pending until confirmed or discarded.
+   * @param keyPackages - One TLS-serialized KeyPackage per device.
+   * @returns Commit, a Welcome for all the devices, and the new GroupInfo.
+   */
+  addUserDevices(
+    groupId: Uint8Array,
+    keyPackages: Uint8Array[],
+  ): MlsAddMemberResult {
+    this.ensureSession();
+    const result: WasmAddMemberResult = add_user_devices(
+      this.sessionId!,
+      groupId,
+      keyPackages,
+    );
+    const out: MlsAddMemberResult = {
+      commit: new Uint8Array(result.commit),
+      welcome: new Uint8Array(result.welcome),
+      groupInfo: new Uint8Array(result.groupInfo),
+    };
+    result.free();
+    return out;
+  }
+
+  /**
+   * Remove every device of each identity in one commit. Leaf indices are
+   * resolved against the current tree, so there is no index to go stale.
+   * Our own leaf is never removed; use `leaveGroup()`.
+   * The commit stays pending until confirmed or discarded.
+   * @throws MlsError with code `member_not_found` if an identity has no
+   *   removable leaf; nothing is committed then.
+   */
+  removeMembersByIdentity(
+    groupId: Uint8Array,
+    identities: Uint8Array[],
+  ): MlsCommitResult {
+    this.ensureSession();
+    return resultToCommit(
+      remove_members_by_identity(this.sessionId!, groupId, identities),
     );
   }
 
+  /**
+   * Rotate our own leaf's HPKE keys (post-compromise security).
+   * The commit stays pending until confirmed or discarded.
+   * @returns Commit (broadcast to the other members) and the new GroupInfo.
+   */
+  selfUpdate(groupId: Uint8Array): MlsCommitResult {
+    this.ensureSession();
+    return resultToCommit(self_update(this.sessionId!, groupId));
+  }
+
   /**
    * Process a Welcome message to join an MLS group.
    * Requires that the KeyPackage used in the Welcome was generated
    * within this session (via `generateSessionKeyPackages()`).
    *
    * @param welcomeTls - TLS-serialized Welcome message.
+   * @param options - Local settings for the group; should match the
+   *   creator's. `requiredCapabilities` is not allowed here.
    * @returns The group ID of the joined group.
    */
-  processWelcome(welcomeTls: Uint8Array): Uint8Array {
+  processWelcome(
+    welcomeTls: Uint8Array,
+    options?: MlsGroupOptions,
+  ): Uint8Array {
     this.ensureSession();
-    return new Uint8Array(process_welcome(this.sessionId!, welcomeTls));
+    return new Uint8Array(
+      process_welcome(this.sessionId!, welcomeTls, options),
+    );
+  }
+
+  /**
+   * Process the Welcome to a group made with `branchGroup()`. The branch's
+   * key schedule includes the parent group's resumption secret, so we must
+   * still hold the parent epoch it was branched from.
+   * @param parentGroupId - The group it was branched from; the Welcome
+   *   doesn't reveal this until the parent's secret is supplied.
+   * @param options - Local settings for the group, as in `processWelcome()`.
+   * @returns The group ID of the joined group.
+   * @throws MlsError with code `psk_not_found` if that parent epoch is no
+   *   longer held, or `invalid_input` if the Welcome is not for a branch of
+   *   `parentGroupId`.
+   */
+  processBranchWelcome(
+    welcomeTls: Uint8Array,
+    parentGroupId: Uint8Array,
+    options?: MlsGroupOptions,
+  ): Uint8Array {
+    this.ensureSession();
+    return new Uint8Array(
+      process_branch_welcome(
+        this.sessionId!,
+        welcomeTls,
+        parentGroupId,
+        options,
+      ),
+    );
+  }
+
+  /**
+   * Join a group without a Welcome, from a GroupInfo stored on the server.
+   * Use it to enter public channels or to recover after falling out of sync:
+   * any local copy of the group is replaced, and our old leaf is removed
+   * by the external commit. A join that fails leaves any local copy as it was.
+   *
+   * Unlike other commits, this one is applied to our copy of the group
+   * immediately: there is nothing to confirm or discard. If the server
+   * refuses it, fetch the current GroupInfo and join again.
+   * @param groupInfoTls - TLS-serialized GroupInfo (see `exportGroupInfo()`).
+   * @param ratchetTree - The group's ratchet tree, needed only if the
+   *   GroupInfo was exported without it (see `exportRatchetTree()`).
+   * @param options - Local settings for the group, as in `processWelcome()`.
+   * @returns The joined group ID, the Commit to upload for the existing
+   *   members, and the new epoch's GroupInfo.
+   * @throws MlsError with code `credential_rejected` if the credential
+   *   validator refuses a member; the join is then undone.
+   */
+  joinByExternalCommit(
+    groupInfoTls: Uint8Array,
+    ratchetTree?: Uint8Array,
+    options?: MlsGroupOptions,
+  ): MlsExternalJoinResult {
+    this.ensureSession();
+    const result: WasmExternalJoinResult = join_by_external_commit(
+      this.sessionId!,
+      groupInfoTls,
+      ratchetTree,
+      options,
+    );
+    const out: MlsExternalJoinResult = {
+      groupId: new Uint8Array(result.groupId),
+      commit: new Uint8Array(result.commit),
+      groupInfo: new Uint8Array(result.groupInfo),
+    };
+    result.free();
+    return out;
   }
 
   /**
    * Encrypt a plaintext message for the group.
    * @param groupId - The group to encrypt for.
    * @param plaintext - The message to encrypt.
+   * @param aad - Authenticated data sent in the clear but bound to the
+   *   ciphertext (e.g. message ID, channel ID, reply-to reference), so the
+   *   server can't move or relabel the message.
    * @returns TLS-serialized MLS ciphertext.
+   * @throws MlsError with code `pending_proposal` while a proposal is queued
+   *   for the group (see `proposeAdd()`); messages can be sent again once it
+   *   is committed.
    */
-  encryptMessage(groupId: Uint8Array, plaintext: Uint8Array): Uint8Array {
+  encryptMessage(
+    groupId: Uint8Array,
+    plaintext: Uint8Array,
+    aad?: Uint8Array,
+  ): Uint8Array {
     this.ensureSession();
     return new Uint8Array(
-      encrypt_message(this.sessionId!, groupId, plaintext),
+      encrypt_message(this.sessionId!, groupId, plaintext, aad),
     );
   }
 
   /**
    * Process an incoming MLS group message (application, commit, or proposal).
-   * For commits, the group state is automatically updated.
-   * For application messages, returns the decrypted plaintext.
+   * For commits, the group state is automatically updated and `commit`
+   * describes what changed.
+   * For application messages, returns the decrypted plaintext. Application
+   * messages from recent past epochs (see `MlsGroupOptions.maxPastEpochs`)
+   * still decrypt; `epoch` tells which epoch a message was sent in.
+   * A received proposal is queued for the next commit, and until some member
+   * commits it this group can't send messages (see `proposeAdd()`).
    */
   processMessage(
     groupId: Uint8Array,
@@ -271,13 +820,214 @@ export class MlsClient {
       messageType: result.messageType,
       plaintext: new Uint8Array(result.plaintext),
       senderIdentity: new Uint8Array(result.senderIdentity),
+      authenticatedData: new Uint8Array(result.authenticatedData),
+      epoch: Number(result.epoch),
     };
+    const details = result.commitDetails;
+    if (details !== undefined) {
+      out.commit = parseCommitDetails(details);
+    }
     result.free();
     return out;
   }
 
+  // ==================== Proposals ====================
+
+  /**
+   * Propose adding a member without committing. The proposal is queued
+   * locally and must be broadcast; any member may later commit it.
+   *
+   * While a proposal is queued — ours, or one received through
+   * `processMessage()` — `encryptMessage()` fails for every member holding
+   * it. Propose only when a member who can commit will do so promptly, e.g.
+   * with `commitPendingProposals()`.
+   */
+  proposeAdd(
+    groupId: Uint8Array,
+    keyPackageTls: Uint8Array,
+  ): MlsProposalResult {
+    this.ensureSession();
+    return resultToProposal(
+      propose_add(this.sessionId!, groupId, keyPackageTls),
+    );
+  }
+
+  /**
+   * Propose removing the member at `leafIndex` without committing. Like
+   * every proposal, it blocks sending until committed (see `proposeAdd()`).
+   */
+  proposeRemove(groupId: Uint8Array, leafIndex: number): MlsProposalResult {
+    this.ensureSession();
+    return resultToProposal(
+      propose_remove(this.sessionId!, groupId, leafIndex),
+    );
+  }
+
+  /**
+   * Propose refreshing our own leaf keys. Useful when we cannot commit
+   * ourselves, e.g. while another member is the designated committer.
+   * Like every proposal, it blocks sending until committed (see
+   * `proposeAdd()`).
+   */
+  proposeSelfUpdate(groupId: Uint8Array): MlsProposalResult {
+    this.ensureSession();
+    return resultToProposal(propose_self_update(this.sessionId!, groupId));
+  }
+
+  /**
+   * Ask to leave the group. Broadcast the returned proposal; another member
+   * has to commit it. From now on the group refuses to send (`left_group`),
+   * and its local state is deleted once the commit removing us is processed.
+   * Call again to get a fresh proposal if it was not committed in time.
+   * @returns TLS-serialized Remove proposal for our own leaf.
+   */
+  leaveGroup(groupId: Uint8Array): Uint8Array {
+    this.ensureSession();
+    return leave_group(this.sessionId!, groupId);
+  }
+
+  /**
+   * Commit every proposal in the group's queue — our own and those received
+   * through `processMessage()`. The commit stays pending until confirmed or
+   * discarded.
+   * @returns The Commit and new GroupInfo, plus a Welcome if the queue
+   *   contained Add proposals.
+   */
+  commitPendingProposals(groupId: Uint8Array): MlsCommitResult {
+    this.ensureSession();
+    return resultToCommit(commit_pending_proposals(this.sessionId!, groupId));
+  }
+
+  /**
+   * List the proposals queued for the next commit.
+   */
+  getPendingProposals(groupId: Uint8Array): MlsPendingProposal[] {
+    this.ensureSession();
+    const json = get_pending_proposals(this.sessionId!, groupId);
+    const raw = JSON.parse(json) as Array<{
+      proposal_ref: number[];
+      proposal_type: string;
+      sender_index: number | null;
+    }>;
+    return raw.map((p) => ({
+      proposalRef: new Uint8Array(p.proposal_ref),
+      proposalType: p.proposal_type,
+      senderIndex: p.sender_index ?? undefined,
+    }));
+  }
+
+  // ==================== Pre-Shared Keys ====================
+
+  /**
+   * Store an external pre-shared key in the session. Every member must
+   * register the same secret under the same id before processing a commit
+   * or Welcome that uses it. Registering an id again replaces its secret.
+   */
+  registerExternalPsk(pskId: Uint8Array, secret: Uint8Array): void {
+    this.ensureSession();
+    register_external_psk(this.sessionId!, pskId, secret);
+  }
+
+  /**
+   * Forget an external pre-shared key. No-op if it isn't registered.
+   */
+  removeExternalPsk(pskId: Uint8Array): void {
+    this.ensureSession();
+    remove_external_psk(this.sessionId!, pskId);
+  }
+
+  /**
+   * Propose mixing the registered external PSK `pskId` into the next epoch.
+   * The proposal is queued locally and must be broadcast; it is committed
+   * along with any other queued proposals.
+   */
+  proposeExternalPsk(
+    groupId: Uint8Array,
+    pskId: Uint8Array,
+  ): MlsProposalResult {
+    this.ensureSession();
+    return resultToProposal(
+      propose_external_psk(this.sessionId!, groupId, pskId),
+    );
+  }
+
+  /**
+   * Create a new group branched from `parentGroupId`, e.g. a voice room's
+   * subgroup or a re-created channel. Its first epoch includes the parent's
+   * current resumption secret, binding it to the parent's history: only
+   * members holding that parent epoch can join (see
+   * `processBranchWelcome()`). The commit stays pending until confirmed or
+   * discarded; the new group exists locally either way.
+   * @param keyPackages - TLS-serialized KeyPackages of parent members.
+   * @param options - Settings for the new group, as in `createGroup()`.
+   * @returns Commit, a Welcome for the added members, and the GroupInfo.
+   * @throws MlsError with code `member_not_found` if a key package's
+   *   signature key is not in the parent group.
+   */
+  branchGroup(
+    parentGroupId: Uint8Array,
+    groupId: Uint8Array,
+    keyPackages: Uint8Array[],
+    options?: MlsGroupOptions,
+  ): MlsAddMemberResult {
+    this.ensureSession();
+    const result: WasmAddMemberResult = branch_group(
+      this.sessionId!,
+      parentGroupId,
+      groupId,
+      keyPackages,
+      options,
+    );
+    const out: MlsAddMemberResult = {
+      commit: new Uint8Array(result.commit),
+      welcome: new Uint8Array(result.welcome),
+      groupInfo: new Uint8Array(result.groupInfo),
+    };
+    result.free();
+    return out;
+  }
+
+  // ==================== Pending Commits ====================
+
+  /**
+   * Apply our pending commit after the server accepted it. The group moves
+   * to the new epoch; only now is it safe to send any Welcome.
+   * @returns What the commit changed, as for incoming commits.
+   */
+  confirmPendingCommit(groupId: Uint8Array): MlsCommitDetails {
+    this.ensureSession();
+    return parseCommitDetails(confirm_pending_commit(this.sessionId!, groupId));
+  }
+
+  /**
+   * Drop our pending commit, e.g. after the server refused it. The group
+   * stays at its epoch and queued proposals are kept. No-op if nothing is
+   * pending.
+   */
+  discardPendingCommit(groupId: Uint8Array): void {
+    this.ensureSession();
+    discard_pending_commit(this.sessionId!, groupId);
+  }
+
+  /**
+   * Whether we have a commit awaiting `confirmPendingCommit()` or
+   * `discardPendingCommit()`. No other commit can be made until then.
+   */
+  hasPendingCommit(groupId: Uint8Array): boolean {
+    this.ensureSession();
+    return has_pending_commit(this.sessionId!, groupId);
+  }
+
   // ==================== Group Inspection ====================
 
+  /**
+   * False once we have called `leaveGroup()` or been removed from the group.
+   */
+  isGroupActive(groupId: Uint8Array): boolean {
+    this.ensureSession();
+    return is_group_active(this.sessionId!, groupId);
+  }
+
   /**
    * Get the current epoch of an MLS group.
    */
@@ -287,21 +1037,102 @@ export class MlsClient {
   }
 
   /**
-   * Get the list of members in an MLS group.
+   * Number of epochs since our leaf keys were last refreshed, whether by
+   * `selfUpdate()`, a commit of ours that carried an update path, another
+   * member committing our `proposeSelfUpdate()`, or joining.
+   * Use it to schedule periodic key rotation in long-lived groups.
+   */
+  getEpochsSinceSelfUpdate(groupId: Uint8Array): number {
+    this.ensureSession();
+    return Number(epochs_since_self_update(this.sessionId!, groupId));
+  }
+
+  /**
+   * Derive a secret from the group's current epoch via the MLS exporter
+   * (RFC 9420 §8.5). All members derive the same value for the same
+   * label and context, and it changes with every epoch.
+   * @param label - Exporter label, e.g. "cairn-voice".
+   * @param context - Application context bound into the derivation.
+   * @param length - Output length in bytes.
+   * @throws MlsError with code `invalid_input` if `length` is 0 or too large.
+   */
+  exportSecret(
+    groupId: Uint8Array,
+    label: string,
+    context: Uint8Array,
+    length: number,
+  ): Uint8Array {
+    this.ensureSession();
+    return new Uint8Array(
+      export_secret(this.sessionId!, groupId, label, context, length),
+    );
+  }
+
+  /**
+   * The current epoch's authenticator (RFC 9420 §8.7). Every member in the
+   * same epoch of the group holds the same value; a server that forked the
+   * group can't make the branches agree. Compare it out of band, e.g. as a
+   * QR code or through `groupFingerprint()`.
+   */
+  getEpochAuthenticator(groupId: Uint8Array): Uint8Array {
+    this.ensureSession();
+    return new Uint8Array(epoch_authenticator(this.sessionId!, groupId));
+  }
+
+  /**
+   * Turn an epoch authenticator into a safety number people can compare:
+   * six blocks of five digits, or eight emoji. Both sides must be at the
+   * same epoch (show `getEpoch()` next to it). No session needed.
+   */
+  groupFingerprint(
+    epochAuthenticator: Uint8Array,
+    format: MlsFingerprintFormat = "numeric",
+  ): string {
+    this.ensureInitialized();
+    return group_fingerprint(epochAuthenticator, format);
+  }
+
+  /**
+   * Export a signed GroupInfo for the current epoch, for the server copy
+   * used by external joins.
+   * @param withRatchetTree - Embed the ratchet tree so joiners need nothing else.
+   * @returns TLS-serialized MLS message carrying the GroupInfo.
+   */
+  exportGroupInfo(groupId: Uint8Array, withRatchetTree = true): Uint8Array {
+    this.ensureSession();
+    return new Uint8Array(
+      export_group_info(this.sessionId!, groupId, withRatchetTree),
+    );
+  }
+
+  /**
+   * Export the group's ratchet tree (TLS-serialized), for use alongside a
+   * GroupInfo exported without it.
+   */
+  exportRatchetTree(groupId: Uint8Array): Uint8Array {
+    this.ensureSession();
+    return new Uint8Array(export_ratchet_tree(this.sessionId!, groupId));
+  }
+
+  /**
+   * Get the members of an MLS group, one entry per user with their devices.
    */
   getMembers(groupId: Uint8Array): MlsGroupMember[] {
     this.ensureSession();
-    const json = get_members(this.sessionId!, groupId);
-    const raw = JSON.parse(json) as Array<{
-      index: number;
-      identity: number[];
-      signature_key: number[];
-    }>;
-    return raw.map((m) => ({
-      index: m.index,
-      identity: new Uint8Array(m.identity),
-      signatureKey: new Uint8Array(m.signature_key),
-    }));
+    const raw = JSON.parse(get_members(this.sessionId!, groupId)) as RawGroupMember[];
+    return raw.map(toGroupMember);
+  }
+
+  /**
+   * Find the member with an identity, with all of their devices.
+   * @returns `undefined` if the identity is not in the group.
+   */
+  findMember(groupId: Uint8Array, identity: Uint8Array): MlsGroupMember | undefined {
+    this.ensureSession();
+    const raw = JSON.parse(
+      find_member(this.sessionId!, groupId, identity),
+    ) as RawGroupMember | null;
+    return raw === null ? undefined : toGroupMember(raw);
   }
 
   // ==================== Internal ====================
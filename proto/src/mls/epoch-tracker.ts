@@ -15,6 +15,10 @@ export interface EpochState {
   data: Uint8Array;
 }
 
+/**
+ * @deprecated MLS groups now retain past-epoch secrets themselves; set
+ *   `MlsGroupOptions.maxPastEpochs` when creating or joining a group.
+ */
 export class EpochTracker {
   private states: Map<number, EpochState> = new Map();
   private maxEpochs: number;
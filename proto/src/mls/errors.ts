@@ -0,0 +1,19 @@
+/**
+ * Typed errors thrown by the MLS WASM module.
+ *
+ * Every failing `MlsClient` call that reaches WASM throws an `Error` named
+ * "MlsError" with a stable `code`. Branch on `code`, never on `message`.
+ */
+
+import type { MlsError } from "./types.js";
+
+/**
+ * Type guard for errors thrown by the MLS WASM module.
+ */
+export function isMlsError(err: unknown): err is MlsError {
+  return (
+    err instanceof Error &&
+    err.name === "MlsError" &&
+    typeof (err as Partial<MlsError>).code === "string"
+  );
+}
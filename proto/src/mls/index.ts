@@ -8,13 +8,35 @@
 export { MlsClient } from "./client.js";
 export { EpochTracker } from "./epoch-tracker.js";
 export { MessageBuffer } from "./message-buffer.js";
+export { isMlsError } from "./errors.js";
 
 export type {
   MlsCredential,
   MlsKeyPackage,
+  MlsKeyPackageOptions,
+  MlsPooledKeyPackage,
+  MlsKeyPackageInfo,
+  MlsExtensionInfo,
+  MlsExtension,
+  MlsGroupOptions,
   MlsAddMemberResult,
+  MlsAddMembersResult,
   MlsProcessedMessage,
+  MlsCommitDetails,
+  MlsMemberChange,
   MlsGroupMember,
+  MlsMemberDevice,
+  MlsDeviceInfo,
+  MlsFingerprintFormat,
+  MlsStorage,
+  MlsCredentialInfo,
+  MlsCredentialValidator,
+  MlsProposalResult,
+  MlsCommitResult,
+  MlsPendingProposal,
+  MlsExternalJoinResult,
+  MlsError,
+  MlsErrorCode,
 } from "./types.js";
 
 export { exportKeys, importKeys } from "./backup.js";
@@ -2,12 +2,20 @@
  * MLS credential bundle — identity + signing key pair.
  */
 export interface MlsCredential {
-  /** The identity bytes (Ed25519 public key, 32 bytes). */
+  /**
+   * The identity bytes: the Ed25519 identity public key (32 bytes), or a
+   * DID-bound identity produced by `MlsClient.bindDid()`.
+   */
   identity: Uint8Array;
   /** The MLS signing public key (Ed25519, 32 bytes). */
   signingPublicKey: Uint8Array;
   /** The MLS signing private key (Ed25519 seed, 32 bytes). */
   signingPrivateKey: Uint8Array;
+  /**
+   * IANA ciphersuite value the signing key was made for
+   * (see `supported_ciphersuites()`). Defaults to 1, the X25519/Ed25519 suite.
+   */
+  ciphersuite?: number;
 }
 
 /**
@@ -20,6 +28,136 @@ export interface MlsKeyPackage {
   initPrivateKey: Uint8Array;
 }
 
+/**
+ * A KeyPackage generated into the session's pool. Its private keys stay
+ * inside the session.
+ */
+export interface MlsPooledKeyPackage {
+  /** TLS-serialized public KeyPackage (upload to server). */
+  keyPackageData: Uint8Array;
+  /** KeyPackage hash reference, as reported by `takeUsedKeyPackages()`. */
+  hashRef: Uint8Array;
+}
+
+/**
+ * An application-defined MLS extension (type code plus opaque data).
+ */
+export interface MlsExtension {
+  extensionType: number;
+  data: Uint8Array;
+}
+
+/**
+ * An extension reported by `inspectKeyPackage()`.
+ */
+export interface MlsExtensionInfo {
+  extensionType: number;
+  /** openmls name for known types, e.g. `"LastResort"` or `"Unknown(65282)"`. */
+  name: string;
+  /** Contents of application-defined extensions; absent for known types. */
+  data?: Uint8Array;
+}
+
+/**
+ * A validated KeyPackage, as described by `inspectKeyPackage()`.
+ */
+export interface MlsKeyPackageInfo {
+  ciphersuite: number;
+  /** Ed25519 identity public key from the credential. */
+  identity: Uint8Array;
+  /** `did:cairn:` DID claimed by the credential; see `MlsCredentialInfo.did`. */
+  did?: string;
+  /** Home server domain, for DID-bound credentials. */
+  homeServer?: string;
+  /** Device id and label, for device-bound credentials. */
+  deviceId?: string;
+  deviceLabel?: string;
+  signatureKey: Uint8Array;
+  /** Validity period, in seconds since the Unix epoch. */
+  lifetime: { notBefore: number; notAfter: number };
+  lastResort: boolean;
+  capabilities: {
+    ciphersuites: number[];
+    extensions: number[];
+    proposals: number[];
+    credentials: number[];
+  };
+  extensions: MlsExtensionInfo[];
+  leafExtensions: MlsExtensionInfo[];
+  /** KeyPackage hash reference. */
+  hashRef: Uint8Array;
+}
+
+/**
+ * Options for generating KeyPackages. Omitted fields use the openmls defaults;
+ * unknown keys are rejected with `invalid_input`.
+ */
+export interface MlsKeyPackageOptions {
+  /** Validity period in seconds (at most 84 days, the default). */
+  lifetimeSeconds?: number;
+  /**
+   * Mark the package as last-resort: it survives the Welcomes that use it,
+   * so the user stays reachable when the server's pool of one-shot packages
+   * runs out. The server should hand it out without marking it consumed.
+   */
+  lastResort?: boolean;
+  /**
+   * Capabilities advertised by the leaf. Each list replaces the default;
+   * extension types the package itself carries are always included.
+   */
+  capabilities?: {
+    extensions?: number[];
+    proposals?: number[];
+    credentials?: number[];
+  };
+  /** KeyPackage extensions. */
+  extensions?: MlsExtension[];
+  /** Leaf node extensions (carried into the group's ratchet tree). */
+  leafExtensions?: MlsExtension[];
+}
+
+/**
+ * Per-group settings. Omitted fields keep the openmls defaults; unknown keys
+ * are rejected with `invalid_input`. Members joining the group should pass
+ * matching settings, above all the same `wireFormatPolicy`.
+ */
+export interface MlsGroupOptions {
+  /**
+   * How many past epochs' secrets to keep, so application messages sent
+   * just before a commit can still be decrypted after it. Defaults to 5;
+   * 0 keeps only the current epoch. Fixed once the group is created or
+   * joined. A larger window tolerates slower delivery at the cost of
+   * forward secrecy for those epochs.
+   */
+  maxPastEpochs?: number;
+  /** How many skipped messages per sender can still arrive late (default 5). */
+  outOfOrderTolerance?: number;
+  /** How far ahead of the last seen message a sender may jump (default 1000). */
+  maximumForwardDistance?: number;
+  /** Pad encrypted messages to a multiple of this many bytes (default 0, none). */
+  paddingSize?: number;
+  /**
+   * Whether handshake messages (proposals, commits) are encrypted or sent
+   * as public messages the server can read. Application messages are always
+   * encrypted. The `mixed` policies accept both and send the named one.
+   * Defaults to `pureCiphertext`.
+   */
+  wireFormatPolicy?:
+    | "pureCiphertext"
+    | "purePlaintext"
+    | "mixedCiphertext"
+    | "mixedPlaintext";
+  /**
+   * Capabilities every member's leaf must advertise (extension, proposal
+   * and credential type codes). Only valid when creating a group.
+   */
+  requiredCapabilities?: {
+    extensions?: number[];
+    proposals?: number[];
+    credentials?: number[];
+  };
+}
+
 /**
  * Result of adding a member to an MLS group.
  */
@@ -28,6 +166,70 @@ export interface MlsAddMemberResult {
   commit: Uint8Array;
   /** TLS-serialized Welcome message (send to the new member). */
   welcome: Uint8Array;
+  /** TLS-serialized GroupInfo for the new epoch (upload to the server). */
+  groupInfo: Uint8Array;
+}
+
+/**
+ * Result of adding several members in one commit.
+ */
+export interface MlsAddMembersResult {
+  /** TLS-serialized Commit message, absent if no KeyPackage was valid. */
+  commit?: Uint8Array;
+  /** TLS-serialized Welcome for every added member, absent if none were added. */
+  welcome?: Uint8Array;
+  /** TLS-serialized GroupInfo for the new epoch, absent if no commit was made. */
+  groupInfo?: Uint8Array;
+  /** KeyPackages left out of the commit, by position in the input list. */
+  rejected: Array<{ index: number; code: MlsErrorCode; error: string }>;
+}
+
+/**
+ * Result of joining a group by external commit.
+ */
+export interface MlsExternalJoinResult {
+  /** ID of the joined group. */
+  groupId: Uint8Array;
+  /** TLS-serialized Commit message (broadcast to the existing members). */
+  commit: Uint8Array;
+  /** TLS-serialized GroupInfo for the new epoch (upload to the server). */
+  groupInfo: Uint8Array;
+}
+
+/**
+ * A proposal created with `proposeAdd()`, `proposeRemove()` or
+ * `proposeSelfUpdate()`.
+ */
+export interface MlsProposalResult {
+  /** TLS-serialized Proposal message (broadcast to group members). */
+  proposal: Uint8Array;
+  /** Proposal reference, identifying it in the pending queue. */
+  proposalRef: Uint8Array;
+}
+
+/**
+ * Result of a commit: removing members, a self update, or committing
+ * the pending proposal queue.
+ */
+export interface MlsCommitResult {
+  /** TLS-serialized Commit message (broadcast to group members). */
+  commit: Uint8Array;
+  /** TLS-serialized Welcome, present only if members were added. */
+  welcome?: Uint8Array;
+  /** TLS-serialized GroupInfo for the new epoch (upload to the server). */
+  groupInfo: Uint8Array;
+}
+
+/**
+ * A proposal waiting in a group's queue for the next commit.
+ */
+export interface MlsPendingProposal {
+  /** Proposal reference. */
+  proposalRef: Uint8Array;
+  /** Proposal type, e.g. "Add", "Remove", "Update". */
+  proposalType: string;
+  /** Leaf index of the proposing member (absent for external senders). */
+  senderIndex?: number;
 }
 
 /**
@@ -40,16 +242,206 @@ export interface MlsProcessedMessage {
   plaintext: Uint8Array;
   /** Identity of the sender (BasicCredential identity bytes). */
   senderIdentity: Uint8Array;
+  /** Authenticated data the sender attached (empty if none). */
+  authenticatedData: Uint8Array;
+  /**
+   * Epoch the message was sent in. Below the group's current epoch for a
+   * late application message decrypted with retained past-epoch secrets.
+   */
+  epoch: number;
+  /** What the commit changed (only for "commit" messages). */
+  commit?: MlsCommitDetails;
 }
 
 /**
- * A member in an MLS group.
+ * A member affected by a commit.
  */
-export interface MlsGroupMember {
+export interface MlsMemberChange {
   /** Leaf node index in the ratchet tree. */
   index: number;
   /** Identity bytes from the member's credential. */
   identity: Uint8Array;
-  /** The member's signature public key. */
+}
+
+/**
+ * Summary of a commit merged by `processMessage()` or
+ * `confirmPendingCommit()`.
+ */
+export interface MlsCommitDetails {
+  /** The group's epoch after the commit. */
+  epoch: number;
+  /** Members added, with their new leaf indices. */
+  added: MlsMemberChange[];
+  /** Members removed, with the leaf indices they had. */
+  removed: MlsMemberChange[];
+  /** Members that refreshed their leaf keys (including the committer). */
+  updated: MlsMemberChange[];
+  /** Group context extension types added, removed or modified. */
+  extensionsChanged: number[];
+  /** True if the commit removed us; the group is no longer usable. */
+  selfRemoved: boolean;
+  /**
+   * True if this commit beat our own pending commit for the same epoch.
+   * Ours was dropped (don't send its Welcome); redo it in the new epoch.
+   */
+  pendingCommitDiscarded: boolean;
+}
+
+/**
+ * A user in an MLS group, with one leaf per device.
+ */
+export interface MlsGroupMember {
+  /** The user's Ed25519 identity public key, shared by all their devices. */
+  identity: Uint8Array;
+  /** `did:cairn:` DID claimed by the credential; see `MlsCredentialInfo.did`. */
+  did?: string;
+  /** Home server domain, for DID-bound credentials. */
+  homeServer?: string;
+  /** The user's leaves, in tree order. */
+  devices: MlsMemberDevice[];
+}
+
+/**
+ * One device (leaf) of a group member.
+ */
+export interface MlsMemberDevice {
+  /** Leaf node index in the ratchet tree. */
+  index: number;
+  /** The device's signature public key. */
+  signatureKey: Uint8Array;
+  /** Device id, for device-bound credentials (signed by the identity key). */
+  deviceId?: string;
+  /** Display label for the device; may be empty. */
+  deviceLabel?: string;
+}
+
+/** How `MlsClient.groupFingerprint()` renders a group's safety number. */
+export type MlsFingerprintFormat = "numeric" | "emoji";
+
+/**
+ * Identifies one of a user's devices in a DID-bound credential.
+ */
+export interface MlsDeviceInfo {
+  /** Stable id, unique among the user's devices (1-64 bytes). */
+  id: string;
+  /** Display label, e.g. "Work laptop" (up to 64 bytes). */
+  label?: string;
+}
+
+/**
+ * Synchronous key/value store that an MLS session writes its state through to.
+ * Keys and values are opaque bytes. Back it with an in-memory map that is
+ * mirrored to IndexedDB so every group operation is persisted as it happens.
+ *
+ * Each operation's writes arrive together once it succeeds, followed by
+ * `commit()`; a failed operation writes nothing. If `put`, `delete` or
+ * `commit` throws, the session stops accepting calls (code `storage`):
+ * discard the writes since the last `commit()` and create a new session
+ * over the store.
+ */
+export interface MlsStorage {
+  /** Return the stored value, or `undefined` if the key is missing. */
+  get(key: Uint8Array): Uint8Array | undefined;
+  put(key: Uint8Array, value: Uint8Array): void;
+  delete(key: Uint8Array): void;
+  /**
+   * Called after the last `put`/`delete` of an operation. Persist the
+   * writes since the previous call in one transaction, so a crash never
+   * leaves half an operation on disk.
+   */
+  commit?(): void;
+}
+
+/**
+ * A credential presented to the validator registered with
+ * `setCredentialValidator()`.
+ */
+export interface MlsCredentialInfo {
+  /** Identity public key from the credential (empty for non-Basic credentials). */
+  identity: Uint8Array;
+  /** The leaf's signature public key. */
   signatureKey: Uint8Array;
+  /** TLS-serialized credential. */
+  credential: Uint8Array;
+  /**
+   * `did:cairn:` DID claimed by a DID-bound credential. The identity key's
+   * signature over the claim is verified, but not that the DID belongs to
+   * that key: validators MUST resolve the DID document and compare its key
+   * to `identity`.
+   */
+  did?: string;
+  /** Home server domain, for DID-bound credentials. */
+  homeServer?: string;
+  /** Device id and label, for device-bound credentials. */
+  deviceId?: string;
+  deviceLabel?: string;
+}
+
+/**
+ * Decides whether a credential may join or change in a group. Return `true`
+ * to accept; anything else (or throwing) refuses the operation.
+ */
+export type MlsCredentialValidator = (info: MlsCredentialInfo) => boolean;
+
+/**
+ * Stable error codes carried by `MlsError`.
+ */
+export type MlsErrorCode =
+  | "invalid_input"
+  | "unsupported"
+  | "unsupported_ciphersuite"
+  | "session_not_found"
+  /** Called from inside a credential validator, while another call runs. */
+  | "session_busy"
+  | "group_not_found"
+  /** No member holds the given identity or leaf index. */
+  | "member_not_found"
+  | "malformed_message"
+  | "invalid_key_package"
+  | "no_matching_key_package"
+  /** Message is for another epoch; `epoch` is the message's. */
+  | "wrong_epoch"
+  | "wrong_group"
+  | "invalid_signature"
+  /**
+   * A member's credential identity is neither a 32-byte identity key nor a
+   * well-formed Cairn identity.
+   */
+  | "invalid_credential"
+  /** The credential validator refused a member's credential. */
+  | "credential_rejected"
+  | "decryption_failed"
+  /** Already processed, or its keys were deleted for forward secrecy. */
+  | "message_too_old"
+  /** Too many messages skipped from this sender. */
+  | "message_too_far_ahead"
+  | "invalid_commit"
+  /** A pre-shared key the operation needs is not registered in the session. */
+  | "psk_not_found"
+  /** Confirm or discard our pending commit before making another. */
+  | "pending_commit"
+  | "no_pending_commit"
+  /** A queued proposal must be committed before the group can send messages. */
+  | "pending_proposal"
+  /** No queued proposal matches the given reference. */
+  | "proposal_not_found"
+  /** We were removed from the group. */
+  | "evicted"
+  /** We asked to leave with `leaveGroup()` and can no longer send. */
+  | "left_group"
+  | "missing_ratchet_tree"
+  | "invalid_snapshot"
+  | "storage"
+  | "internal";
+
+/**
+ * Error thrown by the MLS WASM module (see `isMlsError()`).
+ */
+export interface MlsError extends Error {
+  name: "MlsError";
+  code: MlsErrorCode;
+  /** Epoch of the offending message, for message processing errors. */
+  epoch?: number;
+  /** Group the error relates to, when known. */
+  groupId?: Uint8Array;
 }